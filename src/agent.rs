@@ -0,0 +1,479 @@
+use crate::book::{BookSampling, OpeningBook};
+use crate::{Board, Player};
+use rand::{Rng, RngCore};
+
+/// A move-selection policy. Agents are shared across rayon workers, so any
+/// randomness comes from the caller-supplied RNG rather than internal state.
+pub trait Agent: Send + Sync {
+    /// Short name used in prompts and reports.
+    fn name(&self) -> String;
+
+    /// Pick a column for `player` on `board`. Only called when at least one
+    /// column is playable.
+    fn choose_move(&self, board: &Board, player: Player, rng: &mut dyn RngCore) -> usize;
+
+    /// Score every playable column, higher being better. Stochastic wrappers
+    /// such as softmax sampling draw from these. Defaults to indifference.
+    fn move_scores(&self, board: &Board, _player: Player) -> Vec<(usize, f64)> {
+        board
+            .valid_moves()
+            .into_iter()
+            .map(|col| (col, 0.0))
+            .collect()
+    }
+
+    /// Like `choose_move`, but also names the policy that actually made the
+    /// decision. Wrappers override this to report which branch fired.
+    fn choose_with_policy(
+        &self,
+        board: &Board,
+        player: Player,
+        rng: &mut dyn RngCore,
+    ) -> (usize, String) {
+        (self.choose_move(board, player, rng), self.name())
+    }
+}
+
+/// Picks uniformly among the playable columns.
+pub struct RandomAgent;
+
+impl Agent for RandomAgent {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn choose_move(&self, board: &Board, _player: Player, rng: &mut dyn RngCore) -> usize {
+        let valid_cols = board.valid_moves();
+        valid_cols[rng.random_range(0..valid_cols.len())]
+    }
+}
+
+/// How the tactical agent chooses among moves that are neither wins, blocks
+/// nor blunders.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Fallback {
+    #[default]
+    Random,
+    /// Favour central columns, which take part in more lines of four.
+    Center,
+}
+
+/// Relative weight of each column for `Fallback::Center`.
+const CENTER_WEIGHTS: [u32; 7] = [1, 2, 3, 4, 3, 2, 1];
+
+/// A one-ply "sensible" opponent: it takes an immediate win, otherwise blocks
+/// the opponent's immediate win, and never drops a disc that lets the
+/// opponent win directly on top of it unless it has no other choice.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TacticalAgent {
+    fallback: Fallback,
+}
+
+impl TacticalAgent {
+    pub fn new(fallback: Fallback) -> Self {
+        Self { fallback }
+    }
+}
+
+/// Score bonuses used by `TacticalAgent::move_scores`.
+const WIN_SCORE: f64 = 100.0;
+const BLOCK_SCORE: f64 = 50.0;
+const BLUNDER_PENALTY: f64 = 50.0;
+
+/// True if `player` dropping in `col` lets the opponent win by playing the
+/// same column right after.
+fn gives_win_above(board: &Board, col: usize, player: Player) -> bool {
+    let mut after = board.clone();
+    if after.play(col, player).is_none() {
+        return false;
+    }
+    let opponent = player.opponent();
+    match after.play(col, opponent) {
+        Some((row, col)) => after.is_winning_move(row, col, opponent),
+        None => false,
+    }
+}
+
+impl Agent for TacticalAgent {
+    fn name(&self) -> String {
+        match self.fallback {
+            Fallback::Random => "tactical".to_string(),
+            Fallback::Center => "tactical-center".to_string(),
+        }
+    }
+
+    fn move_scores(&self, board: &Board, player: Player) -> Vec<(usize, f64)> {
+        let (_, wins) = board.immediate_wins(player);
+        let (_, threats) = board.immediate_wins(player.opponent());
+        board
+            .valid_moves()
+            .into_iter()
+            .map(|col| {
+                let mut score = match self.fallback {
+                    Fallback::Random => 0.0,
+                    Fallback::Center => CENTER_WEIGHTS[col] as f64,
+                };
+                if wins.iter().any(|&(_, c)| c == col) {
+                    score += WIN_SCORE;
+                } else if threats.iter().any(|&(_, c)| c == col) {
+                    score += BLOCK_SCORE;
+                } else if gives_win_above(board, col, player) {
+                    score -= BLUNDER_PENALTY;
+                }
+                (col, score)
+            })
+            .collect()
+    }
+
+    fn choose_move(&self, board: &Board, player: Player, rng: &mut dyn RngCore) -> usize {
+        let (_, wins) = board.immediate_wins(player);
+        if let Some(&(_, col)) = wins.first() {
+            return col;
+        }
+        let (_, threats) = board.immediate_wins(player.opponent());
+        if let Some(&(_, col)) = threats.first() {
+            return col;
+        }
+
+        let valid_cols = board.valid_moves();
+        let safe_cols: Vec<usize> = valid_cols
+            .iter()
+            .copied()
+            .filter(|&col| !gives_win_above(board, col, player))
+            .collect();
+        let candidates = if safe_cols.is_empty() {
+            valid_cols
+        } else {
+            safe_cols
+        };
+
+        match self.fallback {
+            Fallback::Random => candidates[rng.random_range(0..candidates.len())],
+            Fallback::Center => {
+                let total: u32 = candidates.iter().map(|&col| CENTER_WEIGHTS[col]).sum();
+                let mut pick = rng.random_range(0..total);
+                for &col in &candidates {
+                    if pick < CENTER_WEIGHTS[col] {
+                        return col;
+                    }
+                    pick -= CENTER_WEIGHTS[col];
+                }
+                unreachable!("weighted pick exceeded the total weight")
+            }
+        }
+    }
+}
+
+/// Plays uniformly at random with probability `epsilon`, and defers to the
+/// wrapped agent otherwise.
+pub struct EpsilonAgent {
+    epsilon: f64,
+    inner: Box<dyn Agent>,
+}
+
+impl Agent for EpsilonAgent {
+    fn name(&self) -> String {
+        format!("eps:{}:{}", self.epsilon, self.inner.name())
+    }
+
+    fn choose_move(&self, board: &Board, player: Player, rng: &mut dyn RngCore) -> usize {
+        self.choose_with_policy(board, player, rng).0
+    }
+
+    fn move_scores(&self, board: &Board, player: Player) -> Vec<(usize, f64)> {
+        self.inner.move_scores(board, player)
+    }
+
+    fn choose_with_policy(
+        &self,
+        board: &Board,
+        player: Player,
+        rng: &mut dyn RngCore,
+    ) -> (usize, String) {
+        if rng.random::<f64>() < self.epsilon {
+            (
+                RandomAgent.choose_move(board, player, rng),
+                RandomAgent.name(),
+            )
+        } else {
+            self.inner.choose_with_policy(board, player, rng)
+        }
+    }
+}
+
+/// Samples a column with probability proportional to
+/// `exp(score / temperature)` over the wrapped agent's move scores. Low
+/// temperatures approach the best-scored move, high ones approach uniform.
+pub struct SoftmaxAgent {
+    temperature: f64,
+    inner: Box<dyn Agent>,
+}
+
+impl Agent for SoftmaxAgent {
+    fn name(&self) -> String {
+        format!("softmax:{}:{}", self.temperature, self.inner.name())
+    }
+
+    fn choose_move(&self, board: &Board, player: Player, rng: &mut dyn RngCore) -> usize {
+        let scores = self.inner.move_scores(board, player);
+        let best = scores
+            .iter()
+            .map(|&(_, score)| score)
+            .fold(f64::NEG_INFINITY, f64::max);
+        // Shift by the best score so the exponentials cannot overflow.
+        let weights: Vec<f64> = scores
+            .iter()
+            .map(|&(_, score)| ((score - best) / self.temperature).exp())
+            .collect();
+        let mut pick = rng.random::<f64>() * weights.iter().sum::<f64>();
+        for (&(col, _), weight) in scores.iter().zip(&weights) {
+            if pick < *weight {
+                return col;
+            }
+            pick -= weight;
+        }
+        // Rounding can leave a sliver past the last weight.
+        scores[scores.len() - 1].0
+    }
+
+    fn move_scores(&self, board: &Board, player: Player) -> Vec<(usize, f64)> {
+        self.inner.move_scores(board, player)
+    }
+}
+
+/// Plays the first `plies` moves of a match (counted from the empty board)
+/// at random, then hands over to the wrapped agent.
+pub struct OpeningAgent {
+    plies: usize,
+    inner: Box<dyn Agent>,
+}
+
+impl Agent for OpeningAgent {
+    fn name(&self) -> String {
+        format!("opening:{}:{}", self.plies, self.inner.name())
+    }
+
+    fn choose_move(&self, board: &Board, player: Player, rng: &mut dyn RngCore) -> usize {
+        self.choose_with_policy(board, player, rng).0
+    }
+
+    fn move_scores(&self, board: &Board, player: Player) -> Vec<(usize, f64)> {
+        self.inner.move_scores(board, player)
+    }
+
+    fn choose_with_policy(
+        &self,
+        board: &Board,
+        player: Player,
+        rng: &mut dyn RngCore,
+    ) -> (usize, String) {
+        if board.ply() < self.plies {
+            (
+                RandomAgent.choose_move(board, player, rng),
+                RandomAgent.name(),
+            )
+        } else {
+            self.inner.choose_with_policy(board, player, rng)
+        }
+    }
+}
+
+/// Plays a weighted book move while the position is in `book`, then hands
+/// over to the wrapped agent.
+pub struct BookAgent {
+    path: String,
+    book: OpeningBook,
+    inner: Box<dyn Agent>,
+}
+
+impl Agent for BookAgent {
+    fn name(&self) -> String {
+        format!("book:{}:{}", self.path, self.inner.name())
+    }
+
+    fn choose_move(&self, board: &Board, player: Player, rng: &mut dyn RngCore) -> usize {
+        self.choose_with_policy(board, player, rng).0
+    }
+
+    fn move_scores(&self, board: &Board, player: Player) -> Vec<(usize, f64)> {
+        self.inner.move_scores(board, player)
+    }
+
+    fn choose_with_policy(
+        &self,
+        board: &Board,
+        player: Player,
+        rng: &mut dyn RngCore,
+    ) -> (usize, String) {
+        match self.book.pick(board, BookSampling::Weighted, rng) {
+            Some(col) => (col, "book".to_string()),
+            None => self.inner.choose_with_policy(board, player, rng),
+        }
+    }
+}
+
+/// Build an agent from its command-line spec. Base agents are `random`,
+/// `tactical` and `tactical-center`; wrappers take a parameter and the agent
+/// they wrap, and nest: `eps:0.1:tactical`, `softmax:0.5:tactical-center`,
+/// `opening:4:eps:0.05:tactical`, `book:book.bin:tactical`. Names are case
+/// insensitive; the book path is taken as written.
+pub fn parse_agent(spec: &str) -> Result<Box<dyn Agent>, String> {
+    let spec = spec.trim();
+    if let Some((wrapper, rest)) = spec.split_once(':') {
+        let (param, inner) = rest
+            .split_once(':')
+            .ok_or_else(|| format!("Missing wrapped agent in: {}", spec))?;
+        let inner = parse_agent(inner)?;
+        return match wrapper.to_lowercase().as_str() {
+            "eps" => match param.parse::<f64>() {
+                Ok(epsilon) if (0.0..=1.0).contains(&epsilon) => {
+                    Ok(Box::new(EpsilonAgent { epsilon, inner }))
+                }
+                _ => Err(format!("Invalid epsilon (must be in 0..1): {}", param)),
+            },
+            "softmax" => match param.parse::<f64>() {
+                Ok(temperature) if temperature > 0.0 => {
+                    Ok(Box::new(SoftmaxAgent { temperature, inner }))
+                }
+                _ => Err(format!("Invalid temperature (must be > 0): {}", param)),
+            },
+            "opening" => match param.parse::<usize>() {
+                Ok(plies) => Ok(Box::new(OpeningAgent { plies, inner })),
+                Err(_) => Err(format!("Invalid number of opening plies: {}", param)),
+            },
+            "book" => Ok(Box::new(BookAgent {
+                path: param.to_string(),
+                book: OpeningBook::load(std::path::Path::new(param))?,
+                inner,
+            })),
+            _ => Err(format!("Unknown agent wrapper: {}", wrapper)),
+        };
+    }
+
+    match spec.to_lowercase().as_str() {
+        "random" => Ok(Box::new(RandomAgent)),
+        "tactical" => Ok(Box::new(TacticalAgent::new(Fallback::Random))),
+        "tactical-center" => Ok(Box::new(TacticalAgent::new(Fallback::Center))),
+        _ => Err(format!("Unknown agent: {}", spec)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+
+    #[test]
+    fn test_parse_agent() {
+        assert_eq!(parse_agent("random").unwrap().name(), "random");
+        assert_eq!(parse_agent(" Random ").unwrap().name(), "random");
+        assert_eq!(parse_agent("tactical").unwrap().name(), "tactical");
+        assert_eq!(
+            parse_agent("tactical-center").unwrap().name(),
+            "tactical-center"
+        );
+        assert!(parse_agent("minimax").is_err());
+
+        let nested = parse_agent("opening:4:eps:0.1:softmax:2:tactical").unwrap();
+        assert_eq!(nested.name(), "opening:4:eps:0.1:softmax:2:tactical");
+        assert!(parse_agent("eps:1.5:random").is_err());
+        assert!(parse_agent("softmax:0:random").is_err());
+        assert!(parse_agent("book:no-such-book.bin:random").is_err());
+        assert!(parse_agent("opening:x:random").is_err());
+        assert!(parse_agent("eps:0.1").is_err());
+        assert!(parse_agent("greedy:1:random").is_err());
+    }
+
+    #[test]
+    fn test_wrappers_record_policy() {
+        let board = Board::new();
+        let mut rng = StepRng::new(0, 0);
+
+        let always_random = parse_agent("eps:1:tactical").unwrap();
+        let (_, policy) = always_random.choose_with_policy(&board, Player::Yellow, &mut rng);
+        assert_eq!(policy, "random");
+
+        let never_random = parse_agent("eps:0:tactical").unwrap();
+        let (_, policy) = never_random.choose_with_policy(&board, Player::Yellow, &mut rng);
+        assert_eq!(policy, "tactical");
+
+        let opening = parse_agent("opening:1:softmax:1:tactical").unwrap();
+        let (_, policy) = opening.choose_with_policy(&board, Player::Yellow, &mut rng);
+        assert_eq!(policy, "random");
+        let mut after_one = board.clone();
+        after_one.play(3, Player::Yellow);
+        let (_, policy) = opening.choose_with_policy(&after_one, Player::Red, &mut rng);
+        assert_eq!(policy, "softmax:1:tactical");
+    }
+
+    #[test]
+    fn test_softmax_low_temperature_takes_win() {
+        let mut board = Board::new();
+        board.play(0, Player::Yellow);
+        board.play(1, Player::Yellow);
+        board.play(2, Player::Yellow);
+        let agent = parse_agent("softmax:0.1:tactical").unwrap();
+        for seed in 0..20u64 {
+            let mut rng = StepRng::new(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15), 1);
+            assert_eq!(agent.choose_move(&board, Player::Yellow, &mut rng), 3);
+        }
+    }
+
+    #[test]
+    fn test_tactical_takes_win_before_block() {
+        let mut board = Board::new();
+        for col in 0..3 {
+            board.play(col, Player::Red);
+            board.play(col, Player::Yellow);
+        }
+        // Both sides threaten column 3; Yellow to move should win, not block.
+        let mut rng = StepRng::new(0, 1);
+        let col = TacticalAgent::default().choose_move(&board, Player::Yellow, &mut rng);
+        assert_eq!(col, 3);
+    }
+
+    #[test]
+    fn test_tactical_blocks_threat() {
+        let mut board = Board::new();
+        board.play(4, Player::Red);
+        board.play(5, Player::Red);
+        board.play(6, Player::Red);
+        for seed in 0..10 {
+            let mut rng = StepRng::new(seed, 7);
+            let agent = TacticalAgent::new(Fallback::Center);
+            assert_eq!(agent.choose_move(&board, Player::Yellow, &mut rng), 3);
+        }
+    }
+
+    #[test]
+    fn test_tactical_avoids_win_above() {
+        // Red has three on the second row (columns 1-3) and the cells that
+        // complete it, in columns 0 and 4, have nothing beneath them yet.
+        let mut board = Board::new();
+        for (col, bottom) in [(1, Player::Yellow), (2, Player::Red), (3, Player::Yellow)] {
+            board.play(col, bottom);
+            board.play(col, Player::Red);
+        }
+        assert!(gives_win_above(&board, 0, Player::Yellow));
+        assert!(gives_win_above(&board, 4, Player::Yellow));
+        assert!(!gives_win_above(&board, 5, Player::Yellow));
+
+        for seed in 0..50u64 {
+            let mut rng = StepRng::new(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15), 0x1234_5678);
+            let col = TacticalAgent::default().choose_move(&board, Player::Yellow, &mut rng);
+            assert!(col != 0 && col != 4);
+        }
+    }
+
+    #[test]
+    fn test_random_agent_skips_full_columns() {
+        let mut board = Board::new();
+        for _ in 0..6 {
+            board.play(0, Player::Yellow);
+        }
+        let mut rng = StepRng::new(0, 0);
+        let col = RandomAgent.choose_move(&board, Player::Red, &mut rng);
+        assert_ne!(col, 0);
+        assert!(board.can_play(col));
+    }
+}
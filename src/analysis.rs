@@ -0,0 +1,174 @@
+//! Threat analysis beyond the one-ply `immediate_wins`.
+//!
+//! A threat is an empty cell that would complete four in a row for a player,
+//! whether or not a disc can be dropped there yet. In the classic zugzwang
+//! theory of Connect 4, rows are numbered 1-6 from the bottom: when the board
+//! fills up, Yellow (moving first) gets to claim cells on odd rows and Red
+//! cells on even rows, so odd threats favour Yellow and even threats Red.
+
+use crate::{Board, MoveRecord, Player};
+use serde::{Deserialize, Serialize};
+
+/// Whether grid row `row` (0 at the top) is odd when rows are numbered 1-6
+/// from the bottom, as in zugzwang theory.
+fn is_odd_row(row: usize) -> bool {
+    row % 2 == 1
+}
+
+/// Every empty cell that would give `player` four in a row, top to bottom
+/// and left to right.
+pub fn threat_cells(board: &Board, player: Player) -> Vec<(usize, usize)> {
+    let mut cells = Vec::new();
+    for row in 0..6 {
+        for col in 0..7 {
+            if board.grid[row][col].is_none() && board.winning_line(row, col, player).is_some() {
+                cells.push((row, col));
+            }
+        }
+    }
+    cells
+}
+
+/// A player's threats, summarized for features and display.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ThreatSummary {
+    /// Threats on odd rows (1, 3, 5 from the bottom).
+    pub odd: usize,
+    /// Threats on even rows (2, 4, 6 from the bottom).
+    pub even: usize,
+    /// Threats that can be played right now.
+    pub playable: usize,
+    /// Whether the opponent cannot stop all of them: two playable threats, or
+    /// two threats stacked directly on top of each other.
+    pub double: bool,
+}
+
+/// Summarize the threats of `player` on `board`.
+pub fn summarize(board: &Board, player: Player) -> ThreatSummary {
+    let cells = threat_cells(board, player);
+    let playable = |&(row, col): &(usize, usize)| row == 5 || board.grid[row + 1][col].is_some();
+    let stacked = cells
+        .iter()
+        .any(|&(row, col)| row > 0 && cells.contains(&(row - 1, col)));
+    let playable_count = cells.iter().filter(|cell| playable(cell)).count();
+    ThreatSummary {
+        odd: cells.iter().filter(|&&(row, _)| is_odd_row(row)).count(),
+        even: cells.iter().filter(|&&(row, _)| !is_odd_row(row)).count(),
+        playable: playable_count,
+        double: playable_count >= 2 || stacked,
+    }
+}
+
+/// Threats of both players in one position.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ThreatAnalysis {
+    pub yellow: ThreatSummary,
+    pub red: ThreatSummary,
+    /// See `zugzwang_favors`.
+    pub zugzwang: Option<Player>,
+}
+
+impl ThreatAnalysis {
+    pub fn of(board: &Board) -> Self {
+        Self {
+            yellow: summarize(board, Player::Yellow),
+            red: summarize(board, Player::Red),
+            zugzwang: zugzwang_favors(board),
+        }
+    }
+}
+
+/// Which side the zugzwang rule of thumb favours if the board fills up
+/// without either side cashing in a threat: Yellow with an odd threat, Red
+/// with an even one, ignoring threats that sit above an opponent threat in
+/// the same column (the opponent's lower threat gets played first). Yellow's
+/// odd threat takes precedence, as in the classic rules.
+pub fn zugzwang_favors(board: &Board) -> Option<Player> {
+    let yellow = threat_cells(board, Player::Yellow);
+    let red = threat_cells(board, Player::Red);
+    // A threat is undermined if the opponent has a threat lower down its column
+    let useful = |cells: &[(usize, usize)], opponent: &[(usize, usize)], odd: bool| {
+        cells.iter().any(|&(row, col)| {
+            is_odd_row(row) == odd && !opponent.iter().any(|&(r, c)| c == col && r > row)
+        })
+    };
+    if useful(&yellow, &red, true) {
+        Some(Player::Yellow)
+    } else if useful(&red, &yellow, false) {
+        Some(Player::Red)
+    } else {
+        None
+    }
+}
+
+/// Record the threats of the position before each move of a match played
+/// from `start`, like `immediate_win_positions` is.
+pub fn annotate_threats(start: &Board, moves: &mut [MoveRecord]) {
+    let mut board = start.clone();
+    for m in moves {
+        m.threats = Some(ThreatAnalysis::of(&board));
+        board.play(m.usr_move, m.player);
+    }
+}
+
+/// One-line description of a position's threats for match listings.
+pub fn describe(analysis: &ThreatAnalysis) -> String {
+    let side = |summary: &ThreatSummary| {
+        format!(
+            "{} odd/{} even{}",
+            summary.odd,
+            summary.even,
+            if summary.double { ", double" } else { "" }
+        )
+    };
+    let zugzwang = analysis.zugzwang.map_or(String::new(), |player| {
+        format!("; zugzwang favours {:?}", player)
+    });
+    format!(
+        "threats Yellow {}; Red {}{}",
+        side(&analysis.yellow),
+        side(&analysis.red),
+        zugzwang
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threat_cells_include_unplayable_cells() {
+        // Red's three on the second row from the bottom threatens cell (4, 3),
+        // which is not playable until column 4 gets its first disc.
+        let board: Board = "112233".parse().unwrap();
+        assert_eq!(threat_cells(&board, Player::Yellow), vec![(5, 3)]);
+        assert_eq!(threat_cells(&board, Player::Red), vec![(4, 3)]);
+
+        let summary = summarize(&board, Player::Red);
+        assert_eq!((summary.odd, summary.even, summary.playable), (0, 1, 0));
+        assert!(!summary.double);
+        let summary = summarize(&board, Player::Yellow);
+        assert_eq!((summary.odd, summary.even, summary.playable), (1, 0, 1));
+    }
+
+    #[test]
+    fn test_double_threats() {
+        // Yellow on 2, 3, 4 of the bottom row can win on either side.
+        let board: Board = "27374".parse().unwrap();
+        let summary = summarize(&board, Player::Yellow);
+        assert_eq!(summary.playable, 2);
+        assert!(summary.double);
+    }
+
+    #[test]
+    fn test_zugzwang_favors() {
+        // Yellow's only threat is on row 1, which is odd.
+        let board: Board = "112233".parse().unwrap();
+        assert_eq!(zugzwang_favors(&board), Some(Player::Yellow));
+        assert_eq!(zugzwang_favors(&Board::new()), None);
+        assert_eq!(
+            describe(&ThreatAnalysis::of(&board)),
+            "threats Yellow 1 odd/0 even; Red 0 odd/1 even; zugzwang favours Yellow"
+        );
+    }
+}
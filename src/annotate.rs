@@ -0,0 +1,282 @@
+//! Move annotation with a depth-limited search: the value of the position
+//! before and after every move, blunder flags and a per-match accuracy.
+
+use crate::compress::Compression;
+use crate::{load_matches, save_matches, AppConfig, Board, Match, MoveRecord, Player};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Columns tried first by the search: central columns take part in more lines
+/// of four, so they tend to cause earlier cut-offs.
+const MOVE_ORDER: [usize; 7] = [3, 2, 4, 1, 5, 0, 6];
+
+/// Score of a win found with no search depth left; wins found earlier (with
+/// more depth remaining) score higher.
+const WIN_SCORE: i32 = 1000;
+
+/// The game-theoretic value of a position for one side, as far as the
+/// search can see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Value {
+    /// The opponent can force a win within the search depth.
+    Loss,
+    /// No forced result within the search depth, which includes draws.
+    Undecided,
+    /// A win can be forced within the search depth.
+    Win,
+}
+
+impl Value {
+    fn from_score(score: i32) -> Self {
+        match score {
+            s if s > 0 => Value::Win,
+            s if s < 0 => Value::Loss,
+            _ => Value::Undecided,
+        }
+    }
+
+    fn flipped(self) -> Self {
+        match self {
+            Value::Win => Value::Loss,
+            Value::Loss => Value::Win,
+            Value::Undecided => Value::Undecided,
+        }
+    }
+}
+
+/// Search results for one move, from the point of view of its player.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MoveAnnotation {
+    pub before: Value,
+    pub after: Value,
+    /// The move made the position worse: a win became undecided or lost, or
+    /// an undecided position became lost.
+    pub blunder: bool,
+}
+
+/// Negamax with alpha-beta pruning. Returns the score of `board` for
+/// `player` (to move) looking `depth` plies ahead: positive if `player` can
+/// force a win, negative if the opponent can, 0 otherwise.
+fn negamax(board: &mut Board, player: Player, depth: usize, mut alpha: i32, beta: i32) -> i32 {
+    let moves: Vec<usize> = MOVE_ORDER
+        .into_iter()
+        .filter(|&col| board.can_play(col))
+        .collect();
+    if moves.is_empty() || depth == 0 {
+        return 0;
+    }
+
+    for &col in &moves {
+        let (row, col) = board.play(col, player).unwrap();
+        let wins = board.is_winning_move(row, col, player);
+        board.undo(col);
+        if wins {
+            return WIN_SCORE + depth as i32;
+        }
+    }
+
+    for &col in &moves {
+        board.play(col, player);
+        let score = -negamax(board, player.opponent(), depth - 1, -beta, -alpha);
+        board.undo(col);
+        if score > alpha {
+            alpha = score;
+        }
+        if alpha >= beta {
+            break;
+        }
+    }
+    alpha
+}
+
+/// The value of `board` for `player`, who is to move.
+pub fn evaluate(board: &Board, player: Player, depth: usize) -> Value {
+    let mut board = board.clone();
+    Value::from_score(negamax(&mut board, player, depth, -i32::MAX, i32::MAX))
+}
+
+/// Annotate every move of a match played from `start` and return the
+/// match's accuracy: the percentage of moves that kept the value of the
+/// position, or None for a match without moves. Fails on a move that cannot
+/// be played.
+pub fn annotate_moves(
+    start: &Board,
+    moves: &mut [MoveRecord],
+    depth: usize,
+) -> Result<Option<f64>, String> {
+    let mut board = start.clone();
+    let mut accurate = 0;
+    for (ply, m) in moves.iter_mut().enumerate() {
+        let before = evaluate(&board, m.player, depth);
+        let landed = (m.usr_move < 7)
+            .then(|| board.play(m.usr_move, m.player))
+            .flatten();
+        let Some((row, col)) = landed else {
+            return Err(format!(
+                "Ply {}: column {} cannot be played",
+                ply + 1,
+                m.usr_move + 1
+            ));
+        };
+        // The move itself used one ply of the search, so the position after
+        // it is searched one ply less and both values see the same horizon
+        let after = if board.is_winning_move(row, col, m.player) {
+            Value::Win
+        } else {
+            evaluate(&board, m.player.opponent(), depth.saturating_sub(1)).flipped()
+        };
+        // Only a drop in value counts against the player: a win that became
+        // undecided or lost, or an undecided position that became lost
+        let blunder = after < before;
+        if !blunder {
+            accurate += 1;
+        }
+        m.annotation = Some(MoveAnnotation {
+            before,
+            after,
+            blunder,
+        });
+    }
+    Ok((!moves.is_empty()).then(|| 100.0 * accurate as f64 / moves.len() as f64))
+}
+
+/// Annotate all `matches` in parallel.
+pub fn annotate_matches(matches: &mut [Match], depth: usize) -> Result<(), String> {
+    matches.par_iter_mut().try_for_each(|m| {
+        let start = m.start_board();
+        m.accuracy = annotate_moves(&start, &mut m.moves, depth)
+            .map_err(|e| format!("Match {}: {}", m.id, e))?;
+        Ok(())
+    })
+}
+
+/// Short text for a move's annotation in match listings.
+pub fn describe(annotation: &MoveAnnotation) -> String {
+    let name = |value: Value| match value {
+        Value::Win => "win",
+        Value::Undecided => "undecided",
+        Value::Loss => "loss",
+    };
+    format!(
+        "value {} -> {}{}",
+        name(annotation.before),
+        name(annotation.after),
+        if annotation.blunder { "  BLUNDER" } else { "" }
+    )
+}
+
+pub fn do_annotate(config: AppConfig) {
+    let input_file = config.input_file.as_ref().unwrap();
+    let output_path = config
+        .output_file
+        .unwrap_or_else(|| PathBuf::from("annotated.json"));
+    let compression =
+        Compression::for_output(config.compress, config.compression_level, &output_path);
+    if let Err(e) = config.output_format.check_compression(compression) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    let mut matches = load_matches(input_file).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    println!(
+        "Annotating {} matches (search depth {})...",
+        matches.len(),
+        config.search_depth
+    );
+    if let Err(e) = annotate_matches(&mut matches, config.search_depth) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+
+    let blunders = matches
+        .iter()
+        .flat_map(|m| &m.moves)
+        .filter(|m| m.annotation.is_some_and(|a| a.blunder))
+        .count();
+    let accuracies: Vec<f64> = matches.iter().filter_map(|m| m.accuracy).collect();
+    let mean_accuracy = if accuracies.is_empty() {
+        0.0
+    } else {
+        accuracies.iter().sum::<f64>() / accuracies.len() as f64
+    };
+
+    if let Err(e) = save_matches(
+        &matches,
+        config.output_format,
+        &output_path,
+        config.store_immediate_wins,
+        compression,
+        config.checksum,
+    ) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    println!(
+        "Found {} blunders, mean accuracy {:.1}%. Saved to {}",
+        blunders,
+        mean_accuracy,
+        output_path.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(line: &str) -> Vec<MoveRecord> {
+        let cols: Vec<usize> = line
+            .chars()
+            .map(|c| c.to_digit(10).unwrap() as usize - 1)
+            .collect();
+        MoveRecord::alternating(&cols)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        // Yellow has three on the bottom row with both ends open.
+        let board: Board = "27374".parse().unwrap();
+        assert_eq!(evaluate(&board, Player::Red, 4), Value::Loss);
+        assert_eq!(evaluate(&board, Player::Red, 0), Value::Undecided);
+        assert_eq!(evaluate(&Board::new(), Player::Yellow, 4), Value::Undecided);
+    }
+
+    #[test]
+    fn test_annotate_flags_missed_win() {
+        // Yellow could win with column 4 on the seventh ply but plays 7.
+        let mut moves = records("112233774");
+        let accuracy = annotate_moves(&Board::new(), &mut moves, 3)
+            .unwrap()
+            .unwrap();
+
+        let missed = moves[6].annotation.unwrap();
+        assert_eq!(missed.before, Value::Win);
+        assert!(missed.blunder);
+        // Red then fails to block, and Yellow takes the win.
+        assert!(moves[7].annotation.unwrap().blunder);
+        assert_eq!(moves[8].annotation.unwrap().after, Value::Win);
+        assert!(!moves[8].annotation.unwrap().blunder);
+        assert!(accuracy < 100.0);
+        assert!(describe(&missed).ends_with("BLUNDER"));
+    }
+
+    #[test]
+    fn test_annotate_uses_the_same_horizon() {
+        // After 27374 Red cannot stop both of Yellow's threats, but a search
+        // of depth 1 does not see it before Red's move, so it is no blunder.
+        let mut moves = records("273747");
+        annotate_moves(&Board::new(), &mut moves, 1).unwrap();
+        let annotation = moves[5].annotation.unwrap();
+        assert_eq!(annotation.before, Value::Undecided);
+        assert_eq!(annotation.after, Value::Undecided);
+        assert!(!annotation.blunder);
+
+        let mut moves = records("11111111");
+        let error = annotate_moves(&Board::new(), &mut moves, 1).unwrap_err();
+        assert_eq!(error, "Ply 7: column 1 cannot be played");
+    }
+}
@@ -0,0 +1,101 @@
+//! Atomic output files.
+//!
+//! Every output file is written to a temporary file in the same directory,
+//! synced to disk and renamed over the output only once complete, so a crash
+//! never leaves a truncated file under the output's name.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The temporary file that `path` is written to before being renamed into
+/// place, hidden in the same directory so the rename stays on one file system.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// Rename the complete, synced file `temp` to `path` and sync the directory
+/// so the rename itself survives a crash.
+pub fn persist(temp: &Path, path: &Path) -> io::Result<()> {
+    std::fs::rename(temp, path)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    // Not every platform can open a directory to sync it
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// An output file being written at its temporary path. `commit` moves it into
+/// place; dropping it without that deletes what was written.
+pub struct PendingFile {
+    temp: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl PendingFile {
+    /// Create the temporary file for `path`.
+    pub fn create(path: &Path) -> io::Result<(Self, File)> {
+        let temp = temp_path(path);
+        let file = File::create(&temp)?;
+        let pending = PendingFile {
+            temp,
+            path: path.to_path_buf(),
+            committed: false,
+        };
+        Ok((pending, file))
+    }
+
+    /// Sync `file`, the complete temporary file, and rename it to the output
+    /// path.
+    pub fn commit(mut self, file: File) -> io::Result<()> {
+        file.sync_all()?;
+        drop(file);
+        persist(&self.temp, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for PendingFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Like `std::fs::write`, but atomic as described above.
+pub fn write_atomically(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let (pending, mut file) = PendingFile::create(path)?;
+    file.write_all(contents.as_ref())?;
+    pending.commit(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_file() {
+        let path = std::env::temp_dir().join(format!("c4-atomic-{}", std::process::id()));
+        write_atomically(&path, "first").unwrap();
+        write_atomically(&path, "second").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        assert!(!temp_path(&path).exists());
+
+        // A file dropped before it is committed leaves the output as it was
+        let (pending, mut file) = PendingFile::create(&path).unwrap();
+        file.write_all(b"third").unwrap();
+        assert!(temp_path(&path).exists());
+        drop(pending);
+        assert!(!temp_path(&path).exists());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
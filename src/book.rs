@@ -0,0 +1,343 @@
+use crate::compress;
+use crate::{load_matches, AppConfig, Board, Match, Player};
+use rand::{Rng, RngCore};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// First bytes of every book file, followed by a format version byte.
+const MAGIC: &[u8; 4] = b"C4BK";
+const VERSION: u8 = 1;
+
+/// Encode a position as a 49-bit key: 7 bits per column (left to right),
+/// holding a marker bit at the column height with the Yellow discs below it.
+/// Distinct positions always get distinct keys.
+fn position_key(board: &Board, mirrored: bool) -> u64 {
+    let mut key = 0u64;
+    for i in 0..7 {
+        let col = if mirrored { 6 - i } else { i };
+        let mut code = 1u64;
+        for row in (0..6).rev() {
+            match board.grid[row][col] {
+                Some(player) => code = (code << 1) | (player == Player::Yellow) as u64,
+                None => break,
+            }
+        }
+        key |= code << (7 * i);
+    }
+    key
+}
+
+/// The key shared by a position and its mirror image, and whether the
+/// position had to be mirrored to get it.
+fn canonical_key(board: &Board) -> (u64, bool) {
+    let (key, mirrored_key) = (position_key(board, false), position_key(board, true));
+    if mirrored_key < key {
+        (mirrored_key, true)
+    } else {
+        (key, false)
+    }
+}
+
+/// How to pick among the book moves of a position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookSampling {
+    /// Every book move is equally likely.
+    Uniform,
+    /// Moves are picked in proportion to how often they were played.
+    Weighted,
+}
+
+impl FromStr for BookSampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "uniform" => Ok(BookSampling::Uniform),
+            "weighted" => Ok(BookSampling::Weighted),
+            _ => Err(format!("Unknown book sampling: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for BookSampling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BookSampling::Uniform => write!(f, "uniform"),
+            BookSampling::Weighted => write!(f, "weighted"),
+        }
+    }
+}
+
+/// Known good moves for early positions, keyed by canonical position so that
+/// mirror-image positions share an entry.
+#[derive(Debug, Default, PartialEq)]
+pub struct OpeningBook {
+    /// Canonical key -> (column in canonical orientation, weight).
+    entries: HashMap<u64, Vec<(u8, u32)>>,
+}
+
+impl OpeningBook {
+    /// Count the moves played in the first `depth` plies of `matches`. Feed it
+    /// games between strong agents so frequent lines are good lines.
+    pub fn build(matches: &[Match], depth: usize) -> Self {
+        let mut book = OpeningBook::default();
+        for m in matches {
+            let mut board = m.start_board();
+            let plies = depth.saturating_sub(board.ply());
+            for record in m.moves.iter().take(plies) {
+                book.add(&board, record.usr_move, 1);
+                board.play(record.usr_move, record.player);
+            }
+        }
+        book
+    }
+
+    fn add(&mut self, board: &Board, col: usize, weight: u32) {
+        let (key, mirrored) = canonical_key(board);
+        let col = if mirrored { 6 - col } else { col } as u8;
+        let moves = self.entries.entry(key).or_default();
+        match moves.iter_mut().find(|(c, _)| *c == col) {
+            Some((_, w)) => *w += weight,
+            None => moves.push((col, weight)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The book moves for `board` as (column, weight), oriented like `board`.
+    pub fn moves(&self, board: &Board) -> Option<Vec<(usize, u32)>> {
+        let (key, mirrored) = canonical_key(board);
+        let moves = self.entries.get(&key)?;
+        Some(
+            moves
+                .iter()
+                .map(|&(col, weight)| {
+                    let col = col as usize;
+                    (if mirrored { 6 - col } else { col }, weight)
+                })
+                .filter(|&(col, _)| board.can_play(col))
+                .collect(),
+        )
+        .filter(|moves: &Vec<(usize, u32)>| !moves.is_empty())
+    }
+
+    /// Pick a book move for `board`, if it is in the book.
+    pub fn pick(
+        &self,
+        board: &Board,
+        sampling: BookSampling,
+        rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        let moves = self.moves(board)?;
+        match sampling {
+            BookSampling::Uniform => Some(moves[rng.random_range(0..moves.len())].0),
+            BookSampling::Weighted => {
+                let total: u64 = moves.iter().map(|&(_, w)| w as u64).sum();
+                let mut pick = rng.random_range(0..total);
+                moves.iter().find_map(|&(col, w)| {
+                    if pick < w as u64 {
+                        Some(col)
+                    } else {
+                        pick -= w as u64;
+                        None
+                    }
+                })
+            }
+        }
+    }
+
+    /// Walk the book from `start`, picking a book move at each step until
+    /// the position leaves the book. Returns the columns played.
+    pub fn sample_line(
+        &self,
+        start: &Board,
+        sampling: BookSampling,
+        rng: &mut dyn RngCore,
+    ) -> Vec<usize> {
+        let mut board = start.clone();
+        let mut player = start.side_to_move();
+        let mut line = Vec::new();
+        while let Some(col) = self.pick(&board, sampling, rng) {
+            let (row, col) = board.play(col, player).unwrap();
+            line.push(col);
+            if board.is_winning_move(row, col, player) {
+                break;
+            }
+            player = player.opponent();
+        }
+        line
+    }
+
+    /// Serialize as: magic, version, entry count (u32), then per entry sorted
+    /// by key: key (u64), move count (u8) and (column u8, weight u32) pairs.
+    /// All integers are little-endian.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        let mut keys: Vec<&u64> = self.entries.keys().collect();
+        keys.sort();
+        for key in keys {
+            let moves = &self.entries[key];
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.push(moves.len() as u8);
+            for &(col, weight) in moves {
+                bytes.push(col);
+                bytes.extend_from_slice(&weight.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let truncated = || "Opening book is truncated".to_string();
+        if bytes.len() < 9 || &bytes[..4] != MAGIC {
+            return Err("Not an opening book file".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("Unsupported opening book version {}", bytes[4]));
+        }
+        let count = u32::from_le_bytes(bytes[5..9].try_into().unwrap());
+        let mut pos = 9;
+        let mut take = |n: usize| -> Result<&[u8], String> {
+            let slice = bytes.get(pos..pos + n).ok_or_else(truncated)?;
+            pos += n;
+            Ok(slice)
+        };
+
+        let mut book = OpeningBook::default();
+        for _ in 0..count {
+            let key = u64::from_le_bytes(take(8)?.try_into().unwrap());
+            let num_moves = take(1)?[0];
+            let mut moves = Vec::with_capacity(num_moves as usize);
+            for _ in 0..num_moves {
+                let col = take(1)?[0];
+                if col > 6 {
+                    return Err(format!("Invalid column {} in opening book", col));
+                }
+                let weight = u32::from_le_bytes(take(4)?.try_into().unwrap());
+                if weight == 0 {
+                    return Err("Opening book moves must have a weight of at least 1".to_string());
+                }
+                moves.push((col, weight));
+            }
+            book.entries.insert(key, moves);
+        }
+        Ok(book)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes())
+            .map_err(|e| format!("Failed to write opening book {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = compress::read(path)
+            .map_err(|e| format!("Failed to read opening book {}: {}", path.display(), e))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+pub fn do_build_book(config: AppConfig) {
+    let input_file = config.input_file.as_ref().unwrap();
+    let matches = load_matches(input_file).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    let book = OpeningBook::build(&matches, config.depth);
+    let output_path = config
+        .output_file
+        .unwrap_or_else(|| PathBuf::from("book.bin"));
+    if let Err(e) = book.save(&output_path) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    println!(
+        "Built opening book with {} positions up to depth {} from {} matches, saved to {}",
+        book.len(),
+        config.depth,
+        matches.len(),
+        output_path.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MoveRecord;
+    use rand::rngs::mock::StepRng;
+
+    fn make_match(id: usize, cols: &[usize]) -> Match {
+        Match::new(id, MoveRecord::alternating(cols))
+    }
+
+    #[test]
+    fn test_position_key_is_unique_and_mirrors() {
+        let mut left = Board::new();
+        left.play(0, Player::Yellow);
+        let mut right = Board::new();
+        right.play(6, Player::Yellow);
+        let mut red = Board::new();
+        red.play(0, Player::Red);
+
+        assert_ne!(position_key(&left, false), position_key(&right, false));
+        assert_ne!(position_key(&left, false), position_key(&red, false));
+        assert_eq!(position_key(&left, true), position_key(&right, false));
+        assert_eq!(canonical_key(&left).0, canonical_key(&right).0);
+    }
+
+    #[test]
+    fn test_build_merges_mirrored_lines() {
+        // 0-3 and its mirror 6-3 reach the same canonical positions.
+        let matches = vec![make_match(1, &[0, 3, 3]), make_match(2, &[6, 3, 2])];
+        let book = OpeningBook::build(&matches, 2);
+        assert_eq!(book.len(), 2);
+        assert_eq!(book.moves(&Board::new()), Some(vec![(0, 1), (6, 1)]));
+
+        let mut after_edge = Board::new();
+        after_edge.play(6, Player::Yellow);
+        assert_eq!(book.moves(&after_edge), Some(vec![(3, 2)]));
+
+        // Depth 2 stops before the third move.
+        let mut board = after_edge.clone();
+        board.play(3, Player::Red);
+        assert_eq!(book.moves(&board), None);
+    }
+
+    #[test]
+    fn test_round_trip_bytes() {
+        let matches = vec![make_match(1, &[3, 3, 2, 4]), make_match(2, &[0, 1, 2])];
+        let book = OpeningBook::build(&matches, 4);
+        let restored = OpeningBook::from_bytes(&book.to_bytes()).unwrap();
+        assert_eq!(book, restored);
+
+        assert!(OpeningBook::from_bytes(b"nope").is_err());
+        let mut bytes = book.to_bytes();
+        assert!(OpeningBook::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let len = bytes.len();
+        bytes[len - 4..].fill(0);
+        assert!(OpeningBook::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_sample_line_follows_book() {
+        let matches = vec![make_match(1, &[3, 3, 2])];
+        let book = OpeningBook::build(&matches, 3);
+        let mut rng = StepRng::new(0, 1);
+        assert_eq!(
+            book.sample_line(&Board::new(), BookSampling::Weighted, &mut rng),
+            vec![3, 3, 2]
+        );
+        assert_eq!(
+            book.sample_line(&Board::new(), BookSampling::Uniform, &mut rng),
+            vec![3, 3, 2]
+        );
+        assert!("weighted".parse::<BookSampling>().is_ok());
+        assert!("sometimes".parse::<BookSampling>().is_err());
+    }
+}
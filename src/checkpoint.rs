@@ -0,0 +1,362 @@
+//! Checkpointing for long generation runs, so that `--resume` can pick up an
+//! interrupted run where it stopped.
+//!
+//! Next to the output, a run keeps a journal (`<output>.journal.jsonl`, one
+//! JSON match per line) of the matches generated since the last output file
+//! was saved, and a checkpoint (`<output>.checkpoint.json`) recording the
+//! seed and options of the run, how many output files are complete and how
+//! many journal lines are trustworthy. Both are removed once the run finishes.
+
+use crate::resources::Limits;
+use crate::{atomic, Match};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// The progress of a generation run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Seeds the RNG of every match, with the match id.
+    pub seed: u64,
+    /// Id of the run's first match, above 1 when appending.
+    pub first_id: usize,
+    pub num_matches: usize,
+    /// Whether the run adds its matches to an existing output (`--append`).
+    #[serde(default)]
+    pub append: bool,
+    /// The other options deciding which matches are generated and how they
+    /// are saved, as (flag, value), which a resumed run must share.
+    #[serde(default)]
+    pub settings: Vec<(String, String)>,
+    /// Number of output files (shards, or the single output) of the run.
+    pub num_units: usize,
+    /// Output files already saved.
+    pub units_done: usize,
+    /// Matches of the next output file already in the journal.
+    pub journaled: usize,
+}
+
+fn sibling(output: &Path, suffix: &str) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn checkpoint_path(output: &Path) -> PathBuf {
+    sibling(output, ".checkpoint.json")
+}
+
+fn journal_path(output: &Path) -> PathBuf {
+    sibling(output, ".journal.jsonl")
+}
+
+impl Checkpoint {
+    pub fn new(
+        seed: u64,
+        first_id: usize,
+        num_matches: usize,
+        append: bool,
+        settings: Vec<(String, String)>,
+        num_units: usize,
+    ) -> Self {
+        Self {
+            seed,
+            first_id,
+            num_matches,
+            append,
+            settings,
+            num_units,
+            units_done: 0,
+            journaled: 0,
+        }
+    }
+
+    /// The checkpoint of an interrupted run writing `output`, if any.
+    pub fn load(output: &Path) -> Result<Option<Self>, String> {
+        let path = checkpoint_path(output);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read checkpoint {}: {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse checkpoint {}: {}", path.display(), e))
+    }
+
+    /// Replace the checkpoint file atomically, so that a crash leaves either
+    /// the old or the new checkpoint.
+    fn save(&self, output: &Path) -> Result<(), String> {
+        let path = checkpoint_path(output);
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        atomic::write_atomically(&path, json)
+            .map_err(|e| format!("Failed to write checkpoint {}: {}", path.display(), e))
+    }
+}
+
+/// Remove the checkpoint and journal of a finished run writing `output`.
+pub fn clear(output: &Path) {
+    let _ = std::fs::remove_file(checkpoint_path(output));
+    let _ = std::fs::remove_file(journal_path(output));
+}
+
+/// Read the first `count` matches of the journal, and rewrite it with only
+/// those so that lines cut short by a crash are dropped.
+fn recover_journal(output: &Path, count: usize) -> Result<Vec<Match>, String> {
+    let path = journal_path(output);
+    let error = |e: String| format!("Failed to read journal {}: {}", path.display(), e);
+    let mut matches = Vec::with_capacity(count);
+    if count > 0 {
+        let reader = BufReader::new(File::open(&path).map_err(|e| error(e.to_string()))?);
+        for line in reader.lines().take(count) {
+            let line = line.map_err(|e| error(e.to_string()))?;
+            matches.push(serde_json::from_str(&line).map_err(|e| error(e.to_string()))?);
+        }
+        if matches.len() < count {
+            return Err(error(format!(
+                "expected {} matches, found {}",
+                count,
+                matches.len()
+            )));
+        }
+    }
+    let mut journal = File::create(&path).map_err(|e| error(e.to_string()))?;
+    append_journal(&mut journal, &matches).map_err(|e| error(e.to_string()))?;
+    Ok(matches)
+}
+
+/// Append `matches` to the journal and make sure they reached the disk
+/// before the checkpoint counts them.
+fn append_journal(journal: &mut File, matches: &[Match]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(&mut *journal);
+    for m in matches {
+        serde_json::to_writer(&mut writer, m)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    journal.sync_data()
+}
+
+/// How a run ended.
+#[derive(Debug, PartialEq)]
+pub enum RunEnd {
+    Complete,
+    /// The deadline passed after `units_done` output files were saved, and
+    /// the `partial` matches of the next one (if any) were saved in it.
+    Stopped {
+        units_done: usize,
+        partial: usize,
+    },
+}
+
+/// Generate the matches of `units`, ranges of match indices (0 for the run's
+/// first match) that each make one output file, starting where `checkpoint`
+/// left off. `generate` makes the matches of a range of indices and `save`
+/// writes one output file given its index and matches. Progress is
+/// checkpointed after every batch of `limits.batch_size` matches and after
+/// each save.
+///
+/// Once `limits.deadline` passes, the matches generated for the current
+/// output file are saved in it and the run stops, leaving the checkpoint in
+/// place so that `--resume` can finish the file later. Every run generates
+/// at least one batch first, so resuming always makes progress even when
+/// recovering the journal takes longer than the time limit.
+pub fn run(
+    output: &Path,
+    mut checkpoint: Checkpoint,
+    units: &[Range<usize>],
+    limits: Limits,
+    generate: impl Fn(Range<usize>) -> Vec<Match>,
+    mut save: impl FnMut(usize, Vec<Match>) -> Result<(), String>,
+) -> Result<RunEnd, String> {
+    let mut matches = recover_journal(output, checkpoint.journaled)?;
+    let mut journal = OpenOptions::new()
+        .append(true)
+        .open(journal_path(output))
+        .map_err(|e| format!("Failed to open journal: {}", e))?;
+    checkpoint.save(output)?;
+
+    let mut generated_any = false;
+    for (index, unit) in units.iter().enumerate().skip(checkpoint.units_done) {
+        let mut next = unit.start + matches.len();
+        while next < unit.end {
+            if generated_any && limits.expired() {
+                let partial = matches.len();
+                if partial > 0 || units.len() == 1 {
+                    save(index, matches)?;
+                }
+                return Ok(RunEnd::Stopped {
+                    units_done: index,
+                    partial,
+                });
+            }
+            let end = (next + limits.batch_size).min(unit.end);
+            let chunk = generate(next..end);
+            append_journal(&mut journal, &chunk)
+                .map_err(|e| format!("Failed to write journal: {}", e))?;
+            matches.extend(chunk);
+            checkpoint.journaled = matches.len();
+            checkpoint.save(output)?;
+            next = end;
+            generated_any = true;
+        }
+
+        save(index, std::mem::take(&mut matches))?;
+        checkpoint.units_done = index + 1;
+        checkpoint.journaled = 0;
+        checkpoint.save(output)?;
+        journal
+            .set_len(0)
+            .map_err(|e| format!("Failed to reset journal: {}", e))?;
+    }
+    Ok(RunEnd::Complete)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::DEFAULT_BATCH_SIZE;
+    use std::time::Instant;
+
+    const UNLIMITED: Limits = Limits {
+        batch_size: DEFAULT_BATCH_SIZE,
+        deadline: None,
+    };
+
+    #[test]
+    fn test_resume_regenerates_nothing_twice() {
+        let dir = std::env::temp_dir().join(format!("c4-checkpoint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("matches.json");
+        let units = [0..1500, 1500..2500];
+        let generate = |ids: Range<usize>| {
+            ids.map(|i| Match::new(i + 1, Vec::new()))
+                .collect::<Vec<_>>()
+        };
+
+        // Interrupt the run while saving the second unit
+        let mut saved = Vec::new();
+        let checkpoint = Checkpoint::new(7, 1, 2500, false, Vec::new(), units.len());
+        let result = run(
+            &output,
+            checkpoint,
+            &units,
+            UNLIMITED,
+            generate,
+            |index, matches| {
+                if index == 1 {
+                    return Err("interrupted".to_string());
+                }
+                saved.push((index, matches.len()));
+                Ok(())
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(saved, vec![(0, 1500)]);
+
+        let checkpoint = Checkpoint::load(&output).unwrap().unwrap();
+        assert_eq!((checkpoint.units_done, checkpoint.journaled), (1, 1000));
+
+        // A line cut short after the last checkpoint is ignored
+        let mut journal = OpenOptions::new()
+            .append(true)
+            .open(journal_path(&output))
+            .unwrap();
+        journal.write_all(b"{\"id\":25").unwrap();
+
+        let generated = std::cell::Cell::new(0);
+        let mut saved = Vec::new();
+        let end = run(
+            &output,
+            checkpoint,
+            &units,
+            UNLIMITED,
+            |ids| {
+                generated.set(generated.get() + ids.len());
+                generate(ids)
+            },
+            |index, matches| {
+                saved.push((index, matches.first().unwrap().id, matches.len()));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(end, RunEnd::Complete);
+        assert_eq!(generated.get(), 0);
+        assert_eq!(saved, vec![(1, 1501, 1000)]);
+
+        clear(&output);
+        assert!(Checkpoint::load(&output).unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_deadline_saves_what_was_generated() {
+        let dir = std::env::temp_dir().join(format!("c4-deadline-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("matches.json");
+        let units = [0..250, 250..500];
+        let generate = |ids: Range<usize>| {
+            ids.map(|i| Match::new(i + 1, Vec::new()))
+                .collect::<Vec<_>>()
+        };
+
+        // A run that saved the first file and journaled 100 matches of the second
+        let checkpoint = Checkpoint {
+            units_done: 1,
+            journaled: 100,
+            ..Checkpoint::new(7, 1, 500, false, Vec::new(), units.len())
+        };
+        checkpoint.save(&output).unwrap();
+        let mut journal = File::create(journal_path(&output)).unwrap();
+        append_journal(&mut journal, &generate(250..350)).unwrap();
+
+        let expired = Limits {
+            batch_size: 100,
+            deadline: Some(Instant::now()),
+        };
+        let mut saved = Vec::new();
+        let end = run(
+            &output,
+            checkpoint,
+            &units,
+            expired,
+            generate,
+            |index, matches| {
+                saved.push((index, matches.len()));
+                Ok(())
+            },
+        )
+        .unwrap();
+        // One batch is generated even though the deadline has passed
+        assert_eq!(
+            end,
+            RunEnd::Stopped {
+                units_done: 1,
+                partial: 200
+            }
+        );
+        assert_eq!(saved, vec![(1, 200)]);
+
+        // The checkpoint is kept, so the file can be finished later
+        let checkpoint = Checkpoint::load(&output).unwrap().unwrap();
+        let mut saved = Vec::new();
+        run(
+            &output,
+            checkpoint,
+            &units,
+            UNLIMITED,
+            generate,
+            |index, matches| {
+                saved.push((index, matches.len()));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(saved, vec![(1, 250)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+//! Parquet output: a columnar layout of the matches for analytics tools such
+//! as DuckDB and Polars.
+//!
+//! Matches are written as two tables. The moves table at the output path has
+//! one row per move; the companion matches table, next to it with a
+//! `_matches` suffix, has one row per match. They join on `match_id`.
+
+use crate::compress;
+use crate::{Match, Player};
+use arrow_array::builder::{ListBuilder, StructBuilder, UInt8Builder};
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, UInt32Array, UInt64Array,
+    UInt8Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Fields, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression as ParquetCompression;
+use parquet::file::properties::WriterProperties;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The (row, col) struct of an immediate-win position, 0-indexed with row 0
+/// at the top, as in the JSON formats.
+fn position_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("row", DataType::UInt8, false),
+        Field::new("col", DataType::UInt8, false),
+    ])
+}
+
+/// One row per move: `match_id`, `ply` (0-indexed within the match),
+/// `player`, `usr_move`, `has_immediate_win`, `immediate_win_positions` and
+/// the `policy` that chose the move, if recorded.
+fn moves_batch(matches: &[Match], store_immediate_wins: bool) -> Result<RecordBatch, ArrowError> {
+    let rows = matches.iter().map(|m| m.moves.len()).sum();
+    let mut match_ids = Vec::with_capacity(rows);
+    let mut plies = Vec::with_capacity(rows);
+    let mut players = Vec::with_capacity(rows);
+    let mut usr_moves = Vec::with_capacity(rows);
+    let mut has_immediate_wins = Vec::with_capacity(rows);
+    let mut policies = Vec::with_capacity(rows);
+    let position_field = Field::new("item", DataType::Struct(position_fields()), false);
+    let mut positions = ListBuilder::new(StructBuilder::from_fields(position_fields(), 0))
+        .with_field(position_field.clone());
+
+    for m in matches {
+        for (ply, record) in m.moves.iter().enumerate() {
+            match_ids.push(m.id as u64);
+            plies.push(ply as u32);
+            players.push(record.player.name());
+            usr_moves.push(record.usr_move as u8);
+            has_immediate_wins.push(record.has_immediate_win);
+            policies.push(record.policy.as_deref());
+
+            if store_immediate_wins {
+                let cells = positions.values();
+                for &(row, col) in &record.immediate_win_positions {
+                    cells
+                        .field_builder::<UInt8Builder>(0)
+                        .unwrap()
+                        .append_value(row as u8);
+                    cells
+                        .field_builder::<UInt8Builder>(1)
+                        .unwrap()
+                        .append_value(col as u8);
+                    cells.append(true);
+                }
+            }
+            positions.append(true);
+        }
+    }
+
+    let schema = Schema::new(vec![
+        Field::new("match_id", DataType::UInt64, false),
+        Field::new("ply", DataType::UInt32, false),
+        Field::new("player", DataType::Utf8, false),
+        Field::new("usr_move", DataType::UInt8, false),
+        Field::new("has_immediate_win", DataType::Boolean, false),
+        Field::new(
+            "immediate_win_positions",
+            DataType::List(Arc::new(position_field)),
+            false,
+        ),
+        Field::new("policy", DataType::Utf8, true),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from(match_ids)),
+        Arc::new(UInt32Array::from(plies)),
+        Arc::new(StringArray::from(players)),
+        Arc::new(UInt8Array::from(usr_moves)),
+        Arc::new(BooleanArray::from(has_immediate_wins)),
+        Arc::new(positions.finish()),
+        Arc::new(StringArray::from(policies)),
+    ];
+    RecordBatch::try_new(Arc::new(schema), columns)
+}
+
+/// One row per match: `match_id`, `num_moves`, `winner` (null for a draw),
+/// the `start` position (null for the empty board) and the annotation
+/// `accuracy`, if any.
+fn matches_batch(matches: &[Match]) -> Result<RecordBatch, ArrowError> {
+    let schema = Schema::new(vec![
+        Field::new("match_id", DataType::UInt64, false),
+        Field::new("num_moves", DataType::UInt32, false),
+        Field::new("winner", DataType::Utf8, true),
+        Field::new("start", DataType::Utf8, true),
+        Field::new("accuracy", DataType::Float64, true),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(
+            matches.iter().map(|m| m.id as u64),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            matches.iter().map(|m| m.moves.len() as u32),
+        )),
+        Arc::new(StringArray::from_iter(
+            matches.iter().map(|m| m.winner().map(Player::name)),
+        )),
+        Arc::new(StringArray::from_iter(
+            matches.iter().map(|m| m.start.as_deref()),
+        )),
+        Arc::new(Float64Array::from_iter(matches.iter().map(|m| m.accuracy))),
+    ];
+    RecordBatch::try_new(Arc::new(schema), columns)
+}
+
+/// Write `batch` as a Snappy-compressed Parquet file.
+fn write_batch<W: Write + Send>(batch: &RecordBatch, writer: W) -> Result<(), String> {
+    let properties = WriterProperties::builder()
+        .set_compression(ParquetCompression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(writer, batch.schema(), Some(properties))
+        .map_err(|e| format!("Failed to write Parquet output: {}", e))?;
+    writer
+        .write(batch)
+        .and_then(|_| writer.close().map(|_| ()))
+        .map_err(|e| format!("Failed to write Parquet output: {}", e))
+}
+
+/// Where the matches table of a moves table at `path` goes: `moves.parquet`
+/// gets `moves_matches.parquet`.
+pub fn matches_table_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}_matches.parquet", stem))
+}
+
+/// Write the moves table to `path` and the matches table next to it. The
+/// files are never wrapped in gzip or zstd, which Parquet readers cannot
+/// read; their columns are compressed already.
+pub fn save_parquet(
+    matches: &[Match],
+    path: &Path,
+    store_immediate_wins: bool,
+) -> Result<(), String> {
+    let tables = [
+        (
+            path.to_path_buf(),
+            moves_batch(matches, store_immediate_wins),
+        ),
+        (matches_table_path(path), matches_batch(matches)),
+    ];
+    for (path, batch) in tables {
+        let batch = batch.map_err(|e| format!("Failed to build Parquet table: {}", e))?;
+        let mut writer = compress::create(&path, None)?;
+        write_batch(&batch, &mut writer)?;
+        writer
+            .finish()
+            .map_err(|e| format!("Failed to write Parquet output: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MoveRecord;
+    use arrow_array::{Array, ListArray, StructArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn sample_matches() -> Vec<Match> {
+        let record = |usr_move, player, wins: Vec<(usize, usize)>| MoveRecord {
+            has_immediate_win: !wins.is_empty(),
+            immediate_win_positions: wins,
+            ..MoveRecord::new(usr_move, player)
+        };
+        vec![
+            Match::new(
+                1,
+                vec![
+                    record(3, Player::Yellow, vec![]),
+                    record(3, Player::Red, vec![]),
+                ],
+            ),
+            Match::new(2, vec![record(0, Player::Red, vec![(5, 1), (4, 2)])]),
+        ]
+    }
+
+    #[test]
+    fn test_moves_batch() {
+        let batch = moves_batch(&sample_matches(), true).unwrap();
+        assert_eq!(batch.num_rows(), 3);
+
+        let plies = batch.column_by_name("ply").unwrap();
+        let plies = plies.as_any().downcast_ref::<UInt32Array>().unwrap();
+        assert_eq!(plies.values(), &[0, 1, 0]);
+
+        let positions = batch.column_by_name("immediate_win_positions").unwrap();
+        let positions = positions.as_any().downcast_ref::<ListArray>().unwrap();
+        assert_eq!(positions.value_length(0), 0);
+        assert_eq!(positions.value_length(2), 2);
+        let cells = positions.value(2);
+        let cells = cells.as_any().downcast_ref::<StructArray>().unwrap();
+        let rows = cells
+            .column(0)
+            .as_any()
+            .downcast_ref::<UInt8Array>()
+            .unwrap();
+        assert_eq!(rows.values(), &[5, 4]);
+
+        let batch = moves_batch(&sample_matches(), false).unwrap();
+        let positions = batch.column_by_name("immediate_win_positions").unwrap();
+        assert_eq!(positions.to_data().child_data()[0].len(), 0);
+    }
+
+    #[test]
+    fn test_matches_table() {
+        let batch = matches_batch(&sample_matches()).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.column_by_name("winner").unwrap().null_count(), 2);
+
+        assert_eq!(
+            matches_table_path(Path::new("out/moves.parquet")),
+            Path::new("out/moves_matches.parquet")
+        );
+    }
+
+    #[test]
+    fn test_save_parquet_round_trip() {
+        let path = std::env::temp_dir().join(format!("c4-moves-{}.parquet", std::process::id()));
+        save_parquet(&sample_matches(), &path, true).unwrap();
+
+        let read = |path: &Path| {
+            let file = std::fs::File::open(path).unwrap();
+            let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .build()
+                .unwrap();
+            reader.map(|batch| batch.unwrap()).collect::<Vec<_>>()
+        };
+        let moves = read(&path);
+        assert_eq!(moves[0], moves_batch(&sample_matches(), true).unwrap());
+        let matches = read(&matches_table_path(&path));
+        assert_eq!(matches[0], matches_batch(&sample_matches()).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(matches_table_path(&path)).unwrap();
+    }
+}
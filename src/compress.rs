@@ -0,0 +1,300 @@
+//! Transparent gzip and zstd compression of the files we write and read.
+//!
+//! Output is compressed when asked for with `--compress` or when the output
+//! path ends in `.gz` or `.zst`. Input is decompressed whenever it starts with
+//! the gzip or zstd magic bytes, whatever its name.
+//!
+//! Output files are written atomically, through the `atomic` module.
+
+use crate::atomic::PendingFile;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// A compression algorithm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+}
+
+impl Codec {
+    fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gz",
+            Codec::Zstd => "zst",
+        }
+    }
+
+    /// The codec named by the extension of `path`, if any.
+    pub fn of_path(path: &Path) -> Option<Codec> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(Codec::Gzip),
+            "zst" => Some(Codec::Zstd),
+            _ => None,
+        }
+    }
+
+    fn default_level(self) -> i32 {
+        match self {
+            Codec::Gzip => 6,
+            Codec::Zstd => 3,
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gzip" | "gz" => Ok(Codec::Gzip),
+            "zstd" | "zst" => Ok(Codec::Zstd),
+            _ => Err(format!(
+                "Unknown compression: {} (expected gzip or zstd)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Codec::Gzip => write!(f, "gzip"),
+            Codec::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// How an output file is compressed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Compression {
+    pub codec: Codec,
+    /// 0-9 for gzip, 1-22 (or negative for faster modes) for zstd.
+    pub level: i32,
+}
+
+impl Compression {
+    /// The compression for an output file at `path`: `codec` if given,
+    /// otherwise the one named by the path's extension, if any. `level`
+    /// overrides the codec's default level.
+    pub fn for_output(codec: Option<Codec>, level: Option<i32>, path: &Path) -> Option<Self> {
+        let codec = codec.or_else(|| Codec::of_path(path))?;
+        Some(Self {
+            codec,
+            level: level.unwrap_or(codec.default_level()),
+        })
+    }
+
+    fn check_level(self) -> Result<(), String> {
+        let valid = match self.codec {
+            Codec::Gzip => (0..=9).contains(&self.level),
+            Codec::Zstd => zstd::compression_level_range().contains(&self.level),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("Invalid {} level: {}", self.codec, self.level))
+        }
+    }
+}
+
+/// `path` with the extension of `codec` appended, e.g. `matches.json.zst`.
+pub fn with_extension(path: PathBuf, codec: Codec) -> PathBuf {
+    let mut name = path.into_os_string();
+    name.push(".");
+    name.push(codec.extension());
+    PathBuf::from(name)
+}
+
+/// `path` without a trailing `.gz` or `.zst`, so that the extension of the
+/// compressed data shows.
+pub fn strip_extension(path: &Path) -> PathBuf {
+    match Codec::of_path(path) {
+        Some(_) => path.with_extension(""),
+        None => path.to_path_buf(),
+    }
+}
+
+enum Encoder {
+    Plain(BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Encoder {
+    /// Write the end of the compressed stream and return the file.
+    fn finish(self) -> io::Result<File> {
+        let writer = match self {
+            Encoder::Plain(writer) => writer,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.into_inner().map_err(|e| e.into_error())
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Encoder::Plain(writer) => writer,
+            Encoder::Gzip(encoder) => encoder,
+            Encoder::Zstd(encoder) => encoder,
+        }
+    }
+}
+
+/// A buffered output file, compressed or not. `finish` must be called to
+/// write the end of the compressed stream and move the file into place;
+/// dropping the writer without it discards what was written.
+pub struct OutputWriter {
+    encoder: Encoder,
+    pending: PendingFile,
+}
+
+impl OutputWriter {
+    /// Complete the compressed stream, sync it to disk and rename it to the
+    /// output path.
+    pub fn finish(self) -> io::Result<()> {
+        let file = self.encoder.finish()?;
+        self.pending.commit(file)
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.writer().flush()
+    }
+}
+
+/// Create the file at `path` for writing, compressed with `compression`.
+/// zstd compresses on as many threads as rayon uses.
+pub fn create(path: &Path, compression: Option<Compression>) -> Result<OutputWriter, String> {
+    // Check the level before creating any file
+    if let Some(compression) = compression {
+        compression.check_level()?;
+    }
+    let (pending, file) =
+        PendingFile::create(path).map_err(|e| format!("Failed to create output file: {}", e))?;
+    let writer = BufWriter::new(file);
+    let encoder = match compression {
+        None => Encoder::Plain(writer),
+        Some(Compression {
+            codec: Codec::Gzip,
+            level,
+        }) => Encoder::Gzip(flate2::write::GzEncoder::new(
+            writer,
+            flate2::Compression::new(level as u32),
+        )),
+        Some(Compression {
+            codec: Codec::Zstd,
+            level,
+        }) => {
+            let encoder = zstd::Encoder::new(writer, level).and_then(|mut encoder| {
+                encoder.multithread(rayon::current_num_threads() as u32)?;
+                Ok(encoder)
+            });
+            match encoder {
+                Ok(encoder) => Encoder::Zstd(encoder),
+                Err(e) => return Err(format!("Failed to start zstd compression: {}", e)),
+            }
+        }
+    };
+    Ok(OutputWriter { encoder, pending })
+}
+
+/// Open the file at `path` for reading, decompressing it if it starts with
+/// gzip or zstd magic bytes.
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = reader.fill_buf()?;
+    Ok(if header.starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(reader)))
+    } else if header.starts_with(&ZSTD_MAGIC) {
+        Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Like `std::fs::read`, decompressing as `open` does.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Like `std::fs::read_to_string`, decompressing as `open` does.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_for_output() {
+        let path = Path::new("matches.json.zst");
+        assert_eq!(
+            Compression::for_output(None, None, path),
+            Some(Compression {
+                codec: Codec::Zstd,
+                level: 3
+            })
+        );
+        assert_eq!(
+            Compression::for_output(Some(Codec::Gzip), Some(9), path).map(|c| c.codec),
+            Some(Codec::Gzip)
+        );
+        assert_eq!(
+            Compression::for_output(None, Some(9), Path::new("m.json")),
+            None
+        );
+        assert_eq!(
+            with_extension(PathBuf::from("m.json"), Codec::Gzip),
+            Path::new("m.json.gz")
+        );
+        assert_eq!(strip_extension(path), Path::new("matches.json"));
+    }
+
+    #[test]
+    fn test_round_trip_detects_compression() {
+        let data = b"[1, 2, 3]".repeat(100);
+        for compression in [
+            None,
+            Compression::for_output(Some(Codec::Gzip), None, Path::new("")),
+            Compression::for_output(Some(Codec::Zstd), Some(19), Path::new("")),
+        ] {
+            let path = std::env::temp_dir().join(format!("c4-compress-{}", std::process::id()));
+            let mut writer = create(&path, compression).unwrap();
+            writer.write_all(&data).unwrap();
+            writer.finish().unwrap();
+
+            let stored = std::fs::read(&path).unwrap();
+            assert_eq!(compression.is_none(), stored == data);
+            assert_eq!(read(&path).unwrap(), data);
+            std::fs::remove_file(&path).unwrap();
+        }
+        // A writer dropped before it finishes leaves no file behind
+        let path = std::env::temp_dir().join(format!("c4-atomic-{}", std::process::id()));
+        let mut writer = create(&path, None).unwrap();
+        writer.write_all(&data).unwrap();
+        drop(writer);
+        assert!(!path.exists() && !crate::atomic::temp_path(&path).exists());
+
+        let path = std::env::temp_dir().join(format!("c4-level-{}", std::process::id()));
+        let gzip = Compression::for_output(Some(Codec::Gzip), Some(10), Path::new(""));
+        assert!(create(&path, gzip).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,87 @@
+//! CSV output with one row per move, for spreadsheets and tools that only
+//! read CSV. Every field is a number or a player name, so nothing needs
+//! quoting.
+
+use crate::{Match, MoveRecord};
+use std::io::{self, Write};
+
+const HEADER: &str = "match_id,ply,player,column,has_immediate_win,immediate_win_mask";
+
+/// The columns of `record`'s immediate wins as a bitmask: bit `c` is set if
+/// dropping a disc in column `c` (0-indexed) would have won.
+pub fn immediate_win_mask(record: &MoveRecord) -> u8 {
+    record
+        .immediate_win_positions
+        .iter()
+        .fold(0, |mask, &(_, col)| mask | 1 << col)
+}
+
+/// Write the header and then each move as soon as it is formatted. `column`
+/// and `ply` are 0-indexed, as in the JSON formats; the mask is 0 when
+/// immediate wins are not stored.
+pub fn write_csv<W: Write>(
+    matches: &[Match],
+    writer: &mut W,
+    store_immediate_wins: bool,
+) -> io::Result<()> {
+    writeln!(writer, "{}", HEADER)?;
+    for m in matches {
+        for (ply, record) in m.moves.iter().enumerate() {
+            let mask = if store_immediate_wins {
+                immediate_win_mask(record)
+            } else {
+                0
+            };
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                m.id,
+                ply,
+                record.player.name(),
+                record.usr_move,
+                record.has_immediate_win as u8,
+                mask
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Player;
+
+    #[test]
+    fn test_write_csv() {
+        let record = |usr_move, player, wins: Vec<(usize, usize)>| MoveRecord {
+            has_immediate_win: !wins.is_empty(),
+            immediate_win_positions: wins,
+            ..MoveRecord::new(usr_move, player)
+        };
+        let matches = vec![
+            Match::new(1, vec![record(3, Player::Yellow, vec![])]),
+            Match::new(
+                2,
+                vec![
+                    record(3, Player::Yellow, vec![]),
+                    record(6, Player::Red, vec![(5, 0), (2, 4)]),
+                ],
+            ),
+        ];
+
+        let mut out = Vec::new();
+        write_csv(&matches, &mut out, true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "match_id,ply,player,column,has_immediate_win,immediate_win_mask\n\
+             1,0,Yellow,3,0,0\n\
+             2,0,Yellow,3,0,0\n\
+             2,1,Red,6,1,17\n"
+        );
+
+        let mut out = Vec::new();
+        write_csv(&matches, &mut out, false).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with("2,1,Red,6,1,0\n"));
+    }
+}
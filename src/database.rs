@@ -0,0 +1,475 @@
+//! SQLite output: `matches` and `moves` tables that can be filtered with SQL,
+//! reading them back, and the `query` command that replays the matches a
+//! filter selects.
+
+use crate::render::Renderer;
+use crate::{atomic, check_starts, print_match_moves, AppConfig, Match, MoveRecord, Player};
+use rusqlite::types::{Type, Value};
+use rusqlite::Error::FromSqlConversionFailure;
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, Row};
+use serde::de::DeserializeOwned;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Every SQLite database file starts with this header.
+pub const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+const SCHEMA: &str = "
+CREATE TABLE matches (
+    id INTEGER PRIMARY KEY,
+    start TEXT,
+    outcome TEXT NOT NULL,
+    num_moves INTEGER NOT NULL,
+    accuracy REAL
+);
+CREATE TABLE moves (
+    match_id INTEGER NOT NULL REFERENCES matches(id),
+    ply INTEGER NOT NULL,
+    player TEXT NOT NULL,
+    usr_move INTEGER NOT NULL,
+    has_immediate_win INTEGER NOT NULL,
+    immediate_win_positions TEXT NOT NULL,
+    policy TEXT,
+    threats TEXT,
+    annotation TEXT,
+    PRIMARY KEY (match_id, ply)
+);
+CREATE INDEX matches_outcome ON matches(outcome);
+CREATE INDEX matches_num_moves ON matches(num_moves);
+";
+
+fn db_error(e: rusqlite::Error) -> String {
+    format!("SQLite error: {}", e)
+}
+
+/// How a match ended, as stored in the `outcome` column.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Yellow,
+    Red,
+    /// A full board, or a match that stopped before anyone won.
+    Draw,
+}
+
+impl Outcome {
+    fn of(m: &Match) -> Self {
+        match m.winner() {
+            Some(Player::Yellow) => Outcome::Yellow,
+            Some(Player::Red) => Outcome::Red,
+            None => Outcome::Draw,
+        }
+    }
+}
+
+impl FromStr for Outcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "yellow" => Ok(Outcome::Yellow),
+            "red" => Ok(Outcome::Red),
+            "draw" => Ok(Outcome::Draw),
+            _ => Err(format!(
+                "Unknown outcome: {} (expected yellow, red or draw)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Yellow => write!(f, "Yellow"),
+            Outcome::Red => write!(f, "Red"),
+            Outcome::Draw => write!(f, "Draw"),
+        }
+    }
+}
+
+/// Whether the file at `path` is a SQLite database rather than JSON.
+pub fn is_database(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| &header == MAGIC)
+}
+
+/// Write `matches` to a new database at `path`, replacing any existing file.
+pub fn save_database(
+    matches: &[Match],
+    path: &Path,
+    store_immediate_wins: bool,
+) -> Result<(), String> {
+    // Build the database beside the output and move it into place when
+    // complete, like every other output file
+    let temp = atomic::temp_path(path);
+    let result = write_database(matches, &temp, store_immediate_wins).and_then(|()| {
+        atomic::persist(&temp, path).map_err(|e| format!("Failed to replace output file: {}", e))
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_database(
+    matches: &[Match],
+    path: &Path,
+    store_immediate_wins: bool,
+) -> Result<(), String> {
+    if path.exists() {
+        std::fs::remove_file(path).map_err(|e| format!("Failed to replace output file: {}", e))?;
+    }
+    let mut conn = Connection::open(path).map_err(db_error)?;
+    conn.execute_batch(SCHEMA).map_err(db_error)?;
+
+    let tx = conn.transaction().map_err(db_error)?;
+    {
+        let mut insert_match = tx
+            .prepare(
+                "INSERT INTO matches (id, start, outcome, num_moves, accuracy)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .map_err(db_error)?;
+        let mut insert_move = tx
+            .prepare("INSERT INTO moves VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")
+            .map_err(db_error)?;
+
+        for m in matches {
+            insert_match
+                .execute(params![
+                    m.id as i64,
+                    m.start,
+                    Outcome::of(m).to_string(),
+                    m.moves.len() as i64,
+                    m.accuracy
+                ])
+                .map_err(db_error)?;
+            for (ply, record) in m.moves.iter().enumerate() {
+                let positions = if store_immediate_wins {
+                    &record.immediate_win_positions[..]
+                } else {
+                    &[]
+                };
+                insert_move
+                    .execute(params![
+                        m.id as i64,
+                        ply as i64,
+                        record.player.name(),
+                        record.usr_move as i64,
+                        record.has_immediate_win,
+                        serde_json::to_string(positions).unwrap(),
+                        record.policy,
+                        record.threats.map(|t| serde_json::to_string(&t).unwrap()),
+                        record
+                            .annotation
+                            .map(|a| serde_json::to_string(&a).unwrap()),
+                    ])
+                    .map_err(db_error)?;
+            }
+        }
+    }
+    tx.commit().map_err(db_error)
+}
+
+fn open(path: &Path) -> Result<Connection, String> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(db_error)
+}
+
+/// Parse the JSON that `save_database` wrote in column `index`.
+fn parse_json<T: DeserializeOwned>(index: usize, text: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(text).map_err(|e| FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+/// Parse the JSON in column `index`, if it is not NULL.
+fn optional_json<T: DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<Option<T>> {
+    row.get::<_, Option<String>>(index)?
+        .map(|text| parse_json(index, &text))
+        .transpose()
+}
+
+fn read_move(row: &Row) -> rusqlite::Result<MoveRecord> {
+    let player = match row.get::<_, String>(0)?.as_str() {
+        name if name == Player::Yellow.name() => Player::Yellow,
+        name if name == Player::Red.name() => Player::Red,
+        name => {
+            let error = format!("unknown player {:?}", name);
+            return Err(FromSqlConversionFailure(0, Type::Text, error.into()));
+        }
+    };
+    Ok(MoveRecord {
+        player,
+        usr_move: row.get::<_, i64>(1)? as usize,
+        has_immediate_win: row.get(2)?,
+        immediate_win_positions: parse_json(3, &row.get::<_, String>(3)?)?,
+        policy: row.get(4)?,
+        threats: optional_json(row, 5)?,
+        annotation: optional_json(row, 6)?,
+    })
+}
+
+/// The matches whose row satisfies the SQL `condition`, in id order, at most
+/// `limit` of them.
+fn select_matches(
+    conn: &Connection,
+    condition: &str,
+    params: &[Value],
+    limit: Option<usize>,
+) -> Result<Vec<Match>, String> {
+    let selected = format!(
+        "FROM matches WHERE {} ORDER BY id LIMIT {}",
+        condition,
+        limit.map_or(-1, |limit| limit as i64)
+    );
+    let sql = format!("SELECT id, start, accuracy {}", selected);
+    let mut select = conn.prepare(&sql).map_err(db_error)?;
+    let mut matches = select
+        .query_map(params_from_iter(params), |row| {
+            Ok(Match {
+                start: row.get(1)?,
+                accuracy: row.get(2)?,
+                ..Match::new(row.get::<_, i64>(0)? as usize, Vec::new())
+            })
+        })
+        .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+        .map_err(db_error)?;
+
+    // All moves in one query rather than one per match, grouped by match in
+    // the order of the matches
+    let sql = format!(
+        "SELECT player, usr_move, has_immediate_win, immediate_win_positions,
+                policy, threats, annotation, match_id
+         FROM moves WHERE match_id IN (SELECT id {}) ORDER BY match_id, ply",
+        selected
+    );
+    let mut select_moves = conn.prepare(&sql).map_err(db_error)?;
+    let mut rows = select_moves
+        .query(params_from_iter(params))
+        .map_err(db_error)?;
+    let mut index = 0;
+    while let Some(row) = rows.next().map_err(db_error)? {
+        let match_id = row.get::<_, i64>(7).map_err(db_error)? as usize;
+        index += matches[index..]
+            .iter()
+            .position(|m| m.id == match_id)
+            .ok_or_else(|| format!("Moves of match {} were not selected", match_id))?;
+        matches[index].moves.push(read_move(row).map_err(db_error)?);
+    }
+    check_starts(&matches)?;
+    Ok(matches)
+}
+
+/// Read back every match of a database written by `save_database`.
+pub fn load_matches(path: &Path) -> Result<Vec<Match>, String> {
+    select_matches(&open(path)?, "1", &[], None)
+}
+
+/// Read back a single match, or None if there is no match `id`.
+pub fn load_match(path: &Path, id: usize) -> Result<Option<Match>, String> {
+    let matches = select_matches(&open(path)?, "id = ?1", &[Value::Integer(id as i64)], None)?;
+    Ok(matches.into_iter().next())
+}
+
+/// Conditions on the `matches` table, all of which must hold.
+#[derive(Debug, Default)]
+pub struct MatchFilter {
+    pub outcome: Option<Outcome>,
+    pub min_plies: Option<usize>,
+    pub max_plies: Option<usize>,
+    /// A raw SQL condition on the columns of `matches`.
+    pub condition: Option<String>,
+}
+
+impl MatchFilter {
+    /// The filter as a WHERE condition and its parameters.
+    fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut conditions = vec!["1".to_string()];
+        let mut params = Vec::new();
+        let mut bind = |condition: &str, value: Value| {
+            params.push(value);
+            conditions.push(format!("{} ?{}", condition, params.len()));
+        };
+        if let Some(outcome) = self.outcome {
+            bind("outcome =", Value::Text(outcome.to_string()));
+        }
+        if let Some(min) = self.min_plies {
+            bind("num_moves >=", Value::Integer(min as i64));
+        }
+        if let Some(max) = self.max_plies {
+            bind("num_moves <=", Value::Integer(max as i64));
+        }
+        if let Some(condition) = &self.condition {
+            conditions.push(format!("({})", condition));
+        }
+        (conditions.join(" AND "), params)
+    }
+
+    /// How many matches pass the filter, and the first `limit` of them.
+    pub fn run(&self, path: &Path, limit: usize) -> Result<(usize, Vec<Match>), String> {
+        let conn = open(path)?;
+        let (condition, params) = self.to_sql();
+        let count: i64 = conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM matches WHERE {}", condition),
+                params_from_iter(&params),
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        let matches = select_matches(&conn, &condition, &params, Some(limit))?;
+        Ok((count as usize, matches))
+    }
+}
+
+pub fn do_query(config: AppConfig) {
+    let input_file = config.input_file.as_ref().unwrap();
+    if !is_database(input_file) {
+        eprintln!(
+            "Error: {} is not a SQLite database, write one with -f sqlite",
+            input_file.display()
+        );
+        std::process::exit(1);
+    }
+
+    let filter = MatchFilter {
+        outcome: config.outcome,
+        min_plies: config.min_plies,
+        max_plies: config.max_plies,
+        condition: config.condition,
+    };
+    let (count, matches) = filter.run(input_file, config.limit).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    if matches.len() < count {
+        println!(
+            "{} matches found, showing the first {}",
+            count,
+            matches.len()
+        );
+    } else {
+        println!("{} matches found", count);
+    }
+    let renderer = Renderer::new(config.render, config.coordinates);
+    for m in &matches {
+        println!();
+        let outcome = match Outcome::of(m) {
+            Outcome::Draw => "Draw".to_string(),
+            winner => format!("{} won", winner),
+        };
+        println!(
+            "##### Match {}: {} in {} plies #####",
+            m.id,
+            outcome,
+            m.moves.len()
+        );
+        if let Some(accuracy) = m.accuracy {
+            println!("Match {} accuracy: {:.1}%", m.id, accuracy);
+        }
+        print_match_moves(&m.start_board(), &m.moves, &renderer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(usr_move: usize, player: Player) -> MoveRecord {
+        MoveRecord {
+            policy: Some("random".to_string()),
+            ..MoveRecord::new(usr_move, player)
+        }
+    }
+
+    /// Yellow wins match 1 on the seventh ply; match 2 is unfinished.
+    fn sample_matches() -> Vec<Match> {
+        let mut won = MoveRecord::alternating(&[0, 1, 0, 1, 0, 1, 0]);
+        for m in &mut won {
+            m.policy = Some("random".to_string());
+        }
+        won[6].has_immediate_win = true;
+        won[6].immediate_win_positions = vec![(2, 0)];
+        vec![
+            Match::new(1, won),
+            Match {
+                start: Some("44".to_string()),
+                accuracy: Some(50.0),
+                ..Match::new(2, vec![record(2, Player::Yellow)])
+            },
+        ]
+    }
+
+    fn temp_database(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("c4-{}-{}.db", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_database_round_trip() {
+        let path = temp_database("round-trip");
+        let matches = sample_matches();
+        save_database(&matches, &path, true).unwrap();
+        assert!(is_database(&path));
+
+        let loaded = load_matches(&path).unwrap();
+        assert_eq!(
+            serde_json::to_string(&loaded).unwrap(),
+            serde_json::to_string(&matches).unwrap()
+        );
+        let second = load_match(&path, 2).unwrap().unwrap();
+        assert_eq!(second.start.as_deref(), Some("44"));
+        assert!(load_match(&path, 3).unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_match_filter() {
+        let path = temp_database("filter");
+        save_database(&sample_matches(), &path, false).unwrap();
+
+        let yellow_wins = MatchFilter {
+            outcome: Some("yellow".parse().unwrap()),
+            max_plies: Some(7),
+            ..MatchFilter::default()
+        };
+        let (count, matches) = yellow_wins.run(&path, 10).unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(matches[0].id, 1);
+        assert!(matches[0].moves[6].immediate_win_positions.is_empty());
+
+        let (count, matches) = MatchFilter::default().run(&path, 1).unwrap();
+        assert_eq!((count, matches.len()), (2, 1));
+
+        let annotated = MatchFilter {
+            condition: Some("accuracy IS NOT NULL".to_string()),
+            ..MatchFilter::default()
+        };
+        assert_eq!(annotated.run(&path, 10).unwrap().1[0].id, 2);
+        assert!(!is_database(Path::new("Cargo.toml")));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_database() {
+        let path = temp_database("corrupt");
+        let corrupt = |sql: &str| {
+            save_database(&sample_matches(), &path, false).unwrap();
+            Connection::open(&path).unwrap().execute(sql, []).unwrap();
+        };
+
+        corrupt("UPDATE matches SET start = '4x' WHERE id = 2");
+        assert!(load_match(&path, 1).unwrap().is_some());
+        assert!(load_match(&path, 2).is_err());
+        assert!(MatchFilter::default().run(&path, 10).is_err());
+
+        corrupt("UPDATE moves SET player = 'Green' WHERE match_id = 2");
+        assert!(load_match(&path, 2).is_err());
+        corrupt("UPDATE moves SET threats = '{' WHERE match_id = 2");
+        assert!(load_match(&path, 2).is_err());
+        corrupt("UPDATE moves SET annotation = '[]' WHERE match_id = 2");
+        assert!(load_match(&path, 2).is_err());
+        assert!(load_matches(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
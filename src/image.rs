@@ -0,0 +1,481 @@
+use crate::render::{highlights, Highlights};
+use crate::{Board, MoveRecord, Player};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+/// Side of one board cell in pixels.
+const CELL: f32 = 60.0;
+/// Space around each board, and between boards in a filmstrip.
+const MARGIN: f32 = 12.0;
+/// Boards per row in a filmstrip grid.
+const FILMSTRIP_COLUMNS: usize = 7;
+/// How long each step of a falling disc is shown in an animation.
+const DROP_FRAME_DELAY_MS: u32 = 40;
+/// The last frame of an animation is held this many times longer.
+const FINAL_HOLD_FACTOR: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    fn hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+const BACKGROUND: Rgb = Rgb(255, 255, 255);
+const FRAME: Rgb = Rgb(30, 80, 180);
+const EMPTY: Rgb = Rgb(235, 240, 250);
+const YELLOW: Rgb = Rgb(245, 200, 0);
+const RED: Rgb = Rgb(210, 40, 40);
+const WIN_LINE: Rgb = Rgb(20, 170, 70);
+const IMMEDIATE_WIN: Rgb = Rgb(200, 40, 200);
+const LAST_DROP: Rgb = Rgb(20, 20, 20);
+
+/// A primitive shape, in pixel coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Rect {
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        color: Rgb,
+    },
+    Circle {
+        cx: f32,
+        cy: f32,
+        r: f32,
+        color: Rgb,
+    },
+    /// A circle outline of the given stroke width, centred on radius `r`.
+    Ring {
+        cx: f32,
+        cy: f32,
+        r: f32,
+        width: f32,
+        color: Rgb,
+    },
+}
+
+/// A drawing that can be written out as SVG or rasterized to pixels.
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub width: u32,
+    pub height: u32,
+    pub shapes: Vec<Shape>,
+}
+
+impl Scene {
+    fn new(width: f32, height: f32) -> Self {
+        let mut scene = Scene {
+            width: width.ceil() as u32,
+            height: height.ceil() as u32,
+            shapes: Vec::new(),
+        };
+        scene.shapes.push(Shape::Rect {
+            x: 0.0,
+            y: 0.0,
+            w: width,
+            h: height,
+            color: BACKGROUND,
+        });
+        scene
+    }
+
+    /// Size of a single board including its margin.
+    fn board_size() -> (f32, f32) {
+        (7.0 * CELL + 2.0 * MARGIN, 6.0 * CELL + 2.0 * MARGIN)
+    }
+
+    /// Draw `board` with its top-left corner (margin included) at (x0, y0).
+    fn draw_board(&mut self, x0: f32, y0: f32, board: &Board, marks: &Highlights) {
+        let (x0, y0) = (x0 + MARGIN, y0 + MARGIN);
+        self.shapes.push(Shape::Rect {
+            x: x0,
+            y: y0,
+            w: 7.0 * CELL,
+            h: 6.0 * CELL,
+            color: FRAME,
+        });
+        let radius = CELL * 0.4;
+        for row in 0..6 {
+            for col in 0..7 {
+                let cx = x0 + (col as f32 + 0.5) * CELL;
+                let cy = y0 + (row as f32 + 0.5) * CELL;
+                let color = match board.grid[row][col] {
+                    Some(Player::Yellow) => YELLOW,
+                    Some(Player::Red) => RED,
+                    None => EMPTY,
+                };
+                self.shapes.push(Shape::Circle {
+                    cx,
+                    cy,
+                    r: radius,
+                    color,
+                });
+                let ring = if marks.winning_line.contains(&(row, col)) {
+                    Some(WIN_LINE)
+                } else if marks.immediate_wins.contains(&(row, col)) {
+                    Some(IMMEDIATE_WIN)
+                } else {
+                    None
+                };
+                if let Some(color) = ring {
+                    self.shapes.push(Shape::Ring {
+                        cx,
+                        cy,
+                        r: radius - 3.0,
+                        width: 6.0,
+                        color,
+                    });
+                }
+                if marks.last_drop == Some((row, col)) {
+                    self.shapes.push(Shape::Circle {
+                        cx,
+                        cy,
+                        r: CELL * 0.08,
+                        color: LAST_DROP,
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            w = self.width,
+            h = self.height
+        );
+        for shape in &self.shapes {
+            let element = match *shape {
+                Shape::Rect { x, y, w, h, color } => format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                    x,
+                    y,
+                    w,
+                    h,
+                    color.hex()
+                ),
+                Shape::Circle { cx, cy, r, color } => format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>",
+                    cx,
+                    cy,
+                    r,
+                    color.hex()
+                ),
+                Shape::Ring {
+                    cx,
+                    cy,
+                    r,
+                    width,
+                    color,
+                } => format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>",
+                    cx,
+                    cy,
+                    r,
+                    color.hex(),
+                    width
+                ),
+            };
+            svg.push_str("  ");
+            svg.push_str(&element);
+            svg.push('\n');
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Rasterize to a row-major RGB buffer. Edges are antialiased with 4x4
+    /// supersampling.
+    pub fn rasterize(&self) -> Vec<u8> {
+        const SAMPLES: usize = 4;
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut pixels = vec![0u8; width * height * 3];
+
+        for shape in &self.shapes {
+            let (x_min, y_min, x_max, y_max) = match *shape {
+                Shape::Rect { x, y, w, h, .. } => (x, y, x + w, y + h),
+                Shape::Circle { cx, cy, r, .. } => (cx - r, cy - r, cx + r, cy + r),
+                Shape::Ring {
+                    cx, cy, r, width, ..
+                } => {
+                    let outer = r + width / 2.0;
+                    (cx - outer, cy - outer, cx + outer, cy + outer)
+                }
+            };
+            let color = match *shape {
+                Shape::Rect { color, .. }
+                | Shape::Circle { color, .. }
+                | Shape::Ring { color, .. } => color,
+            };
+            // Distance from a point to the shape's edge: positive inside,
+            // negative outside.
+            let edge_distance = |px: f32, py: f32| match *shape {
+                Shape::Rect { x, y, w, h, .. } => {
+                    (px - x).min(x + w - px).min(py - y).min(y + h - py)
+                }
+                Shape::Circle { cx, cy, r, .. } => r - (px - cx).hypot(py - cy),
+                Shape::Ring {
+                    cx, cy, r, width, ..
+                } => width / 2.0 - ((px - cx).hypot(py - cy) - r).abs(),
+            };
+
+            let x_range = (x_min.floor().max(0.0) as usize)..(x_max.ceil() as usize).min(width);
+            let y_range = (y_min.floor().max(0.0) as usize)..(y_max.ceil() as usize).min(height);
+            for py in y_range {
+                for px in x_range.clone() {
+                    // Only pixels within half a diagonal of the edge need
+                    // supersampling.
+                    let centre = edge_distance(px as f32 + 0.5, py as f32 + 0.5);
+                    let coverage = if centre > 0.75 {
+                        1.0
+                    } else if centre < -0.75 {
+                        continue;
+                    } else {
+                        let mut hits = 0;
+                        for sy in 0..SAMPLES {
+                            for sx in 0..SAMPLES {
+                                let fx = px as f32 + (sx as f32 + 0.5) / SAMPLES as f32;
+                                let fy = py as f32 + (sy as f32 + 0.5) / SAMPLES as f32;
+                                if edge_distance(fx, fy) >= 0.0 {
+                                    hits += 1;
+                                }
+                            }
+                        }
+                        if hits == 0 {
+                            continue;
+                        }
+                        hits as f32 / (SAMPLES * SAMPLES) as f32
+                    };
+                    let offset = (py * width + px) * 3;
+                    for (channel, value) in [color.0, color.1, color.2].into_iter().enumerate() {
+                        let old = pixels[offset + channel] as f32;
+                        pixels[offset + channel] =
+                            (old + (value as f32 - old) * coverage).round() as u8;
+                    }
+                }
+            }
+        }
+        pixels
+    }
+
+    pub fn write_png(&self, path: &Path) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(&self.rasterize())
+            .map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+
+    /// Write as PNG if `path` ends in `.png`, and as SVG otherwise.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let is_png = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        if is_png {
+            self.write_png(path)
+        } else {
+            std::fs::write(path, self.to_svg())
+        }
+    }
+}
+
+/// The board after `ply` moves of a match played from `start`, with its
+/// highlights.
+pub fn match_scene(start: &Board, moves: &[MoveRecord], ply: usize) -> Scene {
+    let (w, h) = Scene::board_size();
+    let mut scene = Scene::new(w, h);
+    let board = start.with_moves(&moves[..ply]);
+    scene.draw_board(0.0, 0.0, &board, &highlights(&board, moves, ply));
+    scene
+}
+
+/// Every ply of a match (starting from its start position) laid out in a
+/// grid, read left to right, top to bottom.
+pub fn filmstrip_scene(start: &Board, moves: &[MoveRecord]) -> Scene {
+    let frames = moves.len() + 1;
+    let columns = frames.min(FILMSTRIP_COLUMNS);
+    let rows = frames.div_ceil(FILMSTRIP_COLUMNS);
+    let (w, h) = Scene::board_size();
+    let mut scene = Scene::new(w * columns as f32, h * rows as f32);
+    for ply in 0..frames {
+        let board = start.with_moves(&moves[..ply]);
+        let (x, y) = (
+            (ply % FILMSTRIP_COLUMNS) as f32 * w,
+            (ply / FILMSTRIP_COLUMNS) as f32 * h,
+        );
+        scene.draw_board(x, y, &board, &highlights(&board, moves, ply));
+    }
+    scene
+}
+
+/// The frames of an animated replay and how long each is shown, in
+/// milliseconds: the start position, then for every move the disc falling one
+/// row at a time before it settles with its highlights, and finally a longer
+/// hold on the last position so the winning four stays on screen.
+pub fn animation_frames(
+    start: &Board,
+    moves: &[MoveRecord],
+    frame_delay_ms: u32,
+) -> Vec<(Scene, u32)> {
+    let (w, h) = Scene::board_size();
+    let mut frames = vec![(match_scene(start, moves, 0), frame_delay_ms)];
+
+    for ply in 1..=moves.len() {
+        let before = start.with_moves(&moves[..ply - 1]);
+        let m = &moves[ply - 1];
+        let landing_row = (0..6)
+            .rev()
+            .find(|&row| before.grid[row][m.usr_move].is_none());
+
+        for row in 0..landing_row.unwrap_or(0) {
+            let mut falling = before.clone();
+            falling.grid[row][m.usr_move] = Some(m.player);
+            let mut scene = Scene::new(w, h);
+            scene.draw_board(0.0, 0.0, &falling, &Highlights::default());
+            frames.push((scene, DROP_FRAME_DELAY_MS));
+        }
+        frames.push((match_scene(start, moves, ply), frame_delay_ms));
+    }
+
+    if let Some((scene, _)) = frames.last() {
+        frames.push((
+            scene.clone(),
+            frame_delay_ms.saturating_mul(FINAL_HOLD_FACTOR),
+        ));
+    }
+    frames
+}
+
+/// Encode an animated replay of a match as a looping GIF.
+pub fn export_match_gif(
+    start: &Board,
+    moves: &[MoveRecord],
+    path: &Path,
+    frame_delay_ms: u32,
+) -> Result<(), String> {
+    let frames = animation_frames(start, moves, frame_delay_ms);
+    let (width, height) = (frames[0].0.width as u16, frames[0].0.height as u16);
+    let write_error =
+        |e: gif::EncodingError| format!("Failed to write GIF {}: {}", path.display(), e);
+
+    let file =
+        File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut encoder =
+        gif::Encoder::new(BufWriter::new(file), width, height, &[]).map_err(write_error)?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(write_error)?;
+    for (scene, delay_ms) in frames {
+        // Antialiased edges take frames past the 256 colors a GIF palette
+        // holds, so each frame is quantized; the flat board colors survive it.
+        let mut frame = gif::Frame::from_rgb_speed(width, height, &scene.rasterize(), 10);
+        // GIF delays are in hundredths of a second, and viewers treat 0 as
+        // "as fast as possible" or a default, so it is at least 1.
+        frame.delay = (delay_ms / 10).clamp(1, u16::MAX as u32) as u16;
+        encoder.write_frame(&frame).map_err(write_error)?;
+    }
+    Ok(())
+}
+
+/// Write the image requested on the command line for one match: either the
+/// board at `ply` (the final position by default) or the whole filmstrip.
+pub fn export_match_image(
+    start: &Board,
+    moves: &[MoveRecord],
+    path: &Path,
+    ply: Option<usize>,
+    filmstrip: bool,
+) -> Result<(), String> {
+    let scene = if filmstrip {
+        filmstrip_scene(start, moves)
+    } else {
+        let ply = ply.unwrap_or(moves.len());
+        if ply > moves.len() {
+            return Err(format!(
+                "Ply {} is out of range. The match has {} moves",
+                ply,
+                moves.len()
+            ));
+        }
+        match_scene(start, moves, ply)
+    };
+    scene
+        .save(path)
+        .map_err(|e| format!("Failed to write image {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn winning_moves() -> Vec<MoveRecord> {
+        let mut moves = MoveRecord::alternating(&[0, 0, 1, 1, 2, 2, 3]);
+        moves[6].has_immediate_win = true;
+        moves[6].immediate_win_positions = vec![(5, 3)];
+        moves
+    }
+
+    #[test]
+    fn test_match_scene_svg() {
+        let moves = winning_moves();
+        let svg = match_scene(&Board::new(), &moves, moves.len()).to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        // Four winning discs get a green ring.
+        assert_eq!(svg.matches(&WIN_LINE.hex()).count(), 4);
+        assert_eq!(svg.matches(&YELLOW.hex()).count(), 4);
+        assert_eq!(svg.matches(&RED.hex()).count(), 3);
+    }
+
+    #[test]
+    fn test_filmstrip_layout() {
+        let moves = winning_moves();
+        let scene = filmstrip_scene(&Board::new(), &moves);
+        let (w, h) = Scene::board_size();
+        // 8 frames: one full row of 7 and a second row.
+        assert_eq!(scene.width, (w * 7.0).ceil() as u32);
+        assert_eq!(scene.height, (h * 2.0).ceil() as u32);
+    }
+
+    #[test]
+    fn test_animation_frames() {
+        let moves = winning_moves();
+        let frames = animation_frames(&Board::new(), &moves, 500);
+        // Empty board, then per move one frame per empty row above the landing
+        // cell plus the settled board, then the final hold.
+        let drops = 5 + 4 + 5 + 4 + 5 + 4 + 5;
+        assert_eq!(frames.len(), 1 + drops + moves.len() + 1);
+        assert_eq!(frames[1].1, DROP_FRAME_DELAY_MS);
+        assert_eq!(frames.last().unwrap().1, 500 * FINAL_HOLD_FACTOR);
+        assert!(frames.last().unwrap().0.to_svg().contains(&WIN_LINE.hex()));
+
+        let frames = animation_frames(&Board::new(), &moves[..1], u32::MAX);
+        assert_eq!(frames.last().unwrap().1, u32::MAX);
+    }
+
+    #[test]
+    fn test_rasterize_colors() {
+        let moves = winning_moves();
+        let scene = match_scene(&Board::new(), &moves, 1);
+        let pixels = scene.rasterize();
+        assert_eq!(pixels.len(), (scene.width * scene.height * 3) as usize);
+
+        let pixel = |x: f32, y: f32| {
+            let offset = (y as usize * scene.width as usize + x as usize) * 3;
+            Rgb(pixels[offset], pixels[offset + 1], pixels[offset + 2])
+        };
+        assert_eq!(pixel(1.0, 1.0), BACKGROUND);
+        // Yellow's first disc, away from the last-drop marker in its centre.
+        assert_eq!(pixel(MARGIN + 15.0, MARGIN + 5.5 * CELL), YELLOW);
+        assert_eq!(pixel(MARGIN + 1.5 * CELL, MARGIN + 5.5 * CELL), EMPTY);
+    }
+}
@@ -0,0 +1,178 @@
+//! SHA-256 checksums of output files and the `validate` command.
+//!
+//! With `--checksum`, every output file gets a sidecar next to it,
+//! `matches.json.sha256`, in the format of `sha256sum` so that
+//! `sha256sum -c matches.json.sha256` checks it too. `parse` and `validate`
+//! verify the sidecar of their input whenever there is one.
+
+use crate::{atomic, load_matches, shard, AppConfig, Match};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Lowercase hex SHA-256 of the file at `path`.
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// Write the checksum sidecar of the file at `path`.
+pub fn write_sidecar(path: &Path) -> Result<(), String> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let line = format!("{}  {}\n", sha256_file(path)?, name);
+    atomic::write_atomically(&sidecar_path(path), line)
+        .map_err(|e| format!("Failed to write checksum of {}: {}", path.display(), e))
+}
+
+/// Check the file at `path` against its sidecar. Ok(false) if it has none.
+pub fn verify_sidecar(path: &Path) -> Result<bool, String> {
+    let sidecar = sidecar_path(path);
+    if !sidecar.is_file() {
+        return Ok(false);
+    }
+    let text = std::fs::read_to_string(&sidecar)
+        .map_err(|e| format!("Failed to read {}: {}", sidecar.display(), e))?;
+    let expected = text.split_whitespace().next().unwrap_or_default();
+    if sha256_file(path)? != expected.to_lowercase() {
+        return Err(format!(
+            "Checksum mismatch: {} does not match {}, the file is corrupt or incomplete",
+            path.display(),
+            sidecar.display()
+        ));
+    }
+    Ok(true)
+}
+
+/// Problems with the moves of `m`: columns off the board or full, a player
+/// moving twice in a row, or moves after the game was won.
+fn match_problems(m: &Match) -> Vec<String> {
+    let mut board = m.start_board();
+    let mut problems = Vec::new();
+    let mut to_move = board.side_to_move();
+    for (ply, record) in m.moves.iter().enumerate() {
+        let problem = |text: &str| format!("match {}, ply {}: {}", m.id, ply, text);
+        if record.player != to_move {
+            problems.push(problem("played out of turn"));
+        }
+        let landed = (record.usr_move < 7)
+            .then(|| board.play(record.usr_move, record.player))
+            .flatten();
+        let Some((row, col)) = landed else {
+            problems.push(problem(&format!(
+                "column {} cannot be played",
+                record.usr_move
+            )));
+            break;
+        };
+        if board.is_winning_move(row, col, record.player) && ply + 1 < m.moves.len() {
+            problems.push(problem("the game continues after a win"));
+            break;
+        }
+        to_move = record.player.opponent();
+    }
+    problems
+}
+
+/// Check the whole dataset: the checksum sidecar, that it parses, unique
+/// ids, and that every match replays legally.
+pub fn do_validate(config: AppConfig) {
+    let input_file = config.input_file.expect("input file is mandatory");
+    let mut failed = false;
+
+    if shard::is_manifest(&input_file) {
+        println!("Shard checksums are verified against the manifest");
+    } else {
+        match verify_sidecar(&input_file) {
+            Ok(true) => println!("Checksum OK ({})", sidecar_path(&input_file).display()),
+            Ok(false) => println!("No checksum sidecar, skipping the checksum"),
+            Err(e) => {
+                println!("{}", e);
+                failed = true;
+            }
+        }
+    }
+
+    let matches = match load_matches(&input_file) {
+        Ok(matches) => matches,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut ids = HashSet::new();
+    let mut problems: Vec<String> = matches
+        .iter()
+        .filter(|m| !ids.insert(m.id))
+        .map(|m| format!("match {}: duplicate id", m.id))
+        .collect();
+    problems.extend(matches.iter().flat_map(match_problems));
+    for problem in problems.iter().take(10) {
+        println!("{}", problem);
+    }
+    if problems.len() > 10 {
+        println!("... and {} more problems", problems.len() - 10);
+    }
+
+    println!(
+        "{} matches read from {}, {} problems found",
+        matches.len(),
+        input_file.display(),
+        problems.len()
+    );
+    if failed || !problems.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MoveRecord, Player};
+
+    #[test]
+    fn test_sidecar() {
+        let path = std::env::temp_dir().join(format!("c4-sidecar-{}.json", std::process::id()));
+        std::fs::write(&path, "[]").unwrap();
+        assert_eq!(verify_sidecar(&path), Ok(false));
+
+        write_sidecar(&path).unwrap();
+        let sidecar = std::fs::read_to_string(sidecar_path(&path)).unwrap();
+        assert!(sidecar.starts_with(
+            "4f53cda18c2baa0c0354bb5f9a3ecbe5ed12ab4d8e11ba873c2f11161202b945  c4-sidecar-"
+        ));
+        assert_eq!(verify_sidecar(&path), Ok(true));
+
+        std::fs::write(&path, "[ ]").unwrap();
+        assert!(verify_sidecar(&path).is_err());
+        std::fs::remove_file(sidecar_path(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_match_problems() {
+        let record = MoveRecord::new;
+        let (y, r) = (Player::Yellow, Player::Red);
+        let pair = [record(0, y), record(1, r)];
+        let win = [&pair[..], &pair, &pair, &[record(0, y)]].concat();
+        assert!(match_problems(&Match::new(1, win.clone())).is_empty());
+
+        let after_win = [win, vec![record(3, r)]].concat();
+        assert_eq!(match_problems(&Match::new(2, after_win)).len(), 1);
+        assert_eq!(match_problems(&Match::new(3, vec![record(7, y)])).len(), 1);
+        assert_eq!(
+            match_problems(&Match::new(4, vec![record(3, y), record(3, y)])).len(),
+            1
+        );
+    }
+}
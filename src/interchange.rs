@@ -0,0 +1,84 @@
+//! MessagePack and CBOR: compact, self-describing encodings of the same
+//! matches as the JSON formats, which services in other languages can decode
+//! with their standard libraries. Structs are encoded as maps with named
+//! fields, like JSON objects.
+
+use crate::Match;
+use std::io::{Read, Write};
+
+/// The "self-described CBOR" tag (55799) that starts every CBOR file we
+/// write. It tells CBOR apart from MessagePack, and CBOR decoders skip it.
+pub const CBOR_MAGIC: [u8; 3] = [0xd9, 0xd9, 0xf7];
+
+pub fn write_msgpack<W: Write>(matches: &[Match], writer: &mut W) -> Result<(), String> {
+    rmp_serde::encode::write_named(writer, matches)
+        .map_err(|e| format!("Failed to write MessagePack output: {}", e))
+}
+
+pub fn write_cbor<W: Write>(matches: &[Match], writer: &mut W) -> Result<(), String> {
+    writer
+        .write_all(&CBOR_MAGIC)
+        .map_err(|e| format!("Failed to write CBOR output: {}", e))?;
+    ciborium::into_writer(matches, writer)
+        .map_err(|e| format!("Failed to write CBOR output: {}", e))
+}
+
+pub fn read_msgpack<R: Read>(reader: R) -> Result<Vec<Match>, String> {
+    rmp_serde::from_read(reader).map_err(|e| format!("Failed to parse MessagePack: {}", e))
+}
+
+pub fn read_cbor<R: Read>(reader: R) -> Result<Vec<Match>, String> {
+    ciborium::from_reader(reader).map_err(|e| format!("Failed to parse CBOR: {}", e))
+}
+
+/// Whether `byte` can start a MessagePack array (fixarray, array 16 or
+/// array 32), which is how a list of matches begins.
+pub fn is_msgpack_array(byte: u8) -> bool {
+    matches!(byte, 0x90..=0x9f | 0xdc | 0xdd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MoveRecord, Player};
+
+    fn sample_matches() -> Vec<Match> {
+        let moves = vec![MoveRecord {
+            has_immediate_win: true,
+            immediate_win_positions: vec![(5, 2)],
+            policy: Some("tactical".to_string()),
+            ..MoveRecord::new(3, Player::Red)
+        }];
+        vec![Match {
+            start: Some("4".to_string()),
+            accuracy: Some(75.0),
+            ..Match::new(7, moves)
+        }]
+    }
+
+    #[test]
+    fn test_msgpack_round_trip() {
+        let mut bytes = Vec::new();
+        write_msgpack(&sample_matches(), &mut bytes).unwrap();
+        assert!(is_msgpack_array(bytes[0]));
+
+        let matches = read_msgpack(&bytes[..]).unwrap();
+        assert_eq!(
+            serde_json::to_string(&matches).unwrap(),
+            serde_json::to_string(&sample_matches()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_cbor_round_trip() {
+        let mut bytes = Vec::new();
+        write_cbor(&sample_matches(), &mut bytes).unwrap();
+        assert!(bytes.starts_with(&CBOR_MAGIC));
+
+        let matches = read_cbor(&bytes[..]).unwrap();
+        assert_eq!(
+            serde_json::to_string(&matches).unwrap(),
+            serde_json::to_string(&sample_matches()).unwrap()
+        );
+    }
+}
@@ -1,9 +1,47 @@
-use rand::Rng;
+mod agent;
+mod analysis;
+mod annotate;
+mod atomic;
+mod book;
+mod checkpoint;
+mod columnar;
+mod compress;
+mod csv;
+mod database;
+mod image;
+mod integrity;
+mod interchange;
+mod perft;
+mod play;
+mod position;
+mod progress;
+mod render;
+mod report;
+mod resources;
+mod shard;
+mod tournament;
+mod tui;
+
+use agent::{parse_agent, Agent};
+use analysis::ThreatAnalysis;
+use annotate::MoveAnnotation;
+use book::{BookSampling, OpeningBook};
+use checkpoint::{Checkpoint, RunEnd};
+use compress::{Codec, Compression};
+use database::Outcome;
+use progress::{Progress, ProgressMode};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 use rayon::prelude::*;
+use render::{RenderStyle, Renderer};
+use resources::Limits;
 use serde::{Deserialize, Serialize};
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tournament::Schedule;
 
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 enum Player {
@@ -11,6 +49,24 @@ enum Player {
     Red,
 }
 
+impl Player {
+    /// The player who moves after `self`.
+    fn opponent(self) -> Player {
+        match self {
+            Player::Yellow => Player::Red,
+            Player::Red => Player::Yellow,
+        }
+    }
+
+    /// The name used for the player in tabular outputs.
+    fn name(self) -> &'static str {
+        match self {
+            Player::Yellow => "Yellow",
+            Player::Red => "Red",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Board {
     // 6 rows, 7 columns
@@ -23,17 +79,54 @@ enum OutputFormat {
     Json,
     JsonLite,
     Compact,
+    Parquet,
+    Sqlite,
+    Csv,
+    MessagePack,
+    Cbor,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Match {
     moves: Vec<MoveRecord>,
     id: usize,
+    // Position the moves were played from, when not the empty board
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+    // Percentage of moves that kept the position's value, when annotated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    accuracy: Option<f64>,
 }
 
 impl Match {
     pub fn new(id: usize, moves: Vec<MoveRecord>) -> Self {
-        Self { id, moves }
+        Self {
+            id,
+            moves,
+            start: None,
+            accuracy: None,
+        }
+    }
+
+    /// The board before the first recorded move. Starts are validated when
+    /// matches are loaded, so this cannot fail on a loaded match.
+    pub fn start_board(&self) -> Board {
+        self.start.as_deref().map_or_else(Board::new, |start| {
+            start.parse().expect("start position was validated")
+        })
+    }
+
+    /// The player whose last move completed four in a row, or None for a
+    /// draw (or a match that stopped early).
+    pub fn winner(&self) -> Option<Player> {
+        let last = self.moves.last()?;
+        let mut board = self
+            .start_board()
+            .with_moves(&self.moves[..self.moves.len() - 1]);
+        let (row, col) = board.play(last.usr_move, last.player)?;
+        board
+            .is_winning_move(row, col, last.player)
+            .then_some(last.player)
     }
 }
 
@@ -45,6 +138,11 @@ impl FromStr for OutputFormat {
             "json" => Ok(OutputFormat::Json),
             "jsonlite" => Ok(OutputFormat::JsonLite),
             "compact" => Ok(OutputFormat::Compact),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "sqlite" => Ok(OutputFormat::Sqlite),
+            "csv" => Ok(OutputFormat::Csv),
+            "msgpack" | "messagepack" => Ok(OutputFormat::MessagePack),
+            "cbor" => Ok(OutputFormat::Cbor),
             _ => Err(format!("Unknown output format: {}", s)),
         }
     }
@@ -56,6 +154,34 @@ impl std::fmt::Display for OutputFormat {
             OutputFormat::Json => write!(f, "json"),
             OutputFormat::JsonLite => write!(f, "jsonlite"),
             OutputFormat::Compact => write!(f, "compact"),
+            OutputFormat::Parquet => write!(f, "parquet"),
+            OutputFormat::Sqlite => write!(f, "sqlite"),
+            OutputFormat::Csv => write!(f, "csv"),
+            OutputFormat::MessagePack => write!(f, "msgpack"),
+            OutputFormat::Cbor => write!(f, "cbor"),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Whether `load_matches` can read the format back, which adding matches
+    /// to an existing file relies on.
+    fn is_readable(self) -> bool {
+        !matches!(
+            self,
+            OutputFormat::Compact | OutputFormat::Parquet | OutputFormat::Csv
+        )
+    }
+
+    /// Refuse gzip or zstd for SQLite and Parquet: their readers open the
+    /// files directly, and Parquet already compresses its columns.
+    fn check_compression(self, compression: Option<Compression>) -> Result<(), String> {
+        match self {
+            OutputFormat::Sqlite | OutputFormat::Parquet if compression.is_some() => Err(format!(
+                "{} output cannot be compressed with gzip or zstd, other tools could not read it",
+                self
+            )),
+            _ => Ok(()),
         }
     }
 }
@@ -63,6 +189,15 @@ impl std::fmt::Display for OutputFormat {
 enum ToolMode {
     Generation,
     Parsing,
+    Playing,
+    Viewing,
+    Reporting,
+    BookBuilding,
+    Tournament,
+    Annotating,
+    Perft,
+    Querying,
+    Validating,
 }
 
 struct AppConfig {
@@ -73,6 +208,46 @@ struct AppConfig {
     output_file: Option<PathBuf>,
     input_file: Option<PathBuf>,
     id: Option<usize>,
+    agent: String,
+    human_first: bool,
+    yellow_agent: String,
+    red_agent: String,
+    render: RenderStyle,
+    coordinates: bool,
+    image: Option<PathBuf>,
+    ply: Option<usize>,
+    filmstrip: bool,
+    gif: Option<PathBuf>,
+    frame_delay_ms: u32,
+    depth: usize,
+    book: Option<PathBuf>,
+    book_sampling: BookSampling,
+    start_positions: Option<PathBuf>,
+    per_position: Option<usize>,
+    position: Option<String>,
+    tournament_agents: Vec<String>,
+    schedule: Schedule,
+    games_per_pairing: usize,
+    record_threats: bool,
+    search_depth: usize,
+    outcome: Option<Outcome>,
+    min_plies: Option<usize>,
+    max_plies: Option<usize>,
+    condition: Option<String>,
+    limit: usize,
+    compress: Option<Codec>,
+    compression_level: Option<i32>,
+    shard_size: Option<usize>,
+    num_shards: Option<usize>,
+    seed: Option<u64>,
+    resume: bool,
+    append: bool,
+    checksum: bool,
+    quiet: bool,
+    progress_json: bool,
+    threads: Option<usize>,
+    max_memory: Option<u64>,
+    time_limit: Option<Duration>,
 }
 
 impl Default for AppConfig {
@@ -85,6 +260,46 @@ impl Default for AppConfig {
             output_file: None,
             input_file: None,
             id: None,
+            agent: "random".to_string(),
+            human_first: true,
+            yellow_agent: "random".to_string(),
+            red_agent: "random".to_string(),
+            render: RenderStyle::Emoji,
+            coordinates: false,
+            image: None,
+            ply: None,
+            filmstrip: false,
+            gif: None,
+            frame_delay_ms: 500,
+            depth: 8,
+            book: None,
+            book_sampling: BookSampling::Weighted,
+            start_positions: None,
+            per_position: None,
+            position: None,
+            tournament_agents: Vec::new(),
+            schedule: Schedule::RoundRobin,
+            games_per_pairing: 100,
+            record_threats: false,
+            search_depth: 8,
+            outcome: None,
+            min_plies: None,
+            max_plies: None,
+            condition: None,
+            limit: 10,
+            compress: None,
+            compression_level: None,
+            shard_size: None,
+            num_shards: None,
+            seed: None,
+            resume: false,
+            append: false,
+            checksum: false,
+            quiet: false,
+            progress_json: false,
+            threads: None,
+            max_memory: None,
+            time_limit: None,
         }
     }
 }
@@ -118,6 +333,51 @@ impl Board {
         None
     }
 
+    /// Remove the topmost piece from `col`, undoing the last `play` there.
+    /// Returns the (row, col) that was cleared, or None if the column is empty.
+    fn undo(&mut self, col: usize) -> Option<(usize, usize)> {
+        for row in 0..6 {
+            if self.grid[row][col].is_some() {
+                self.grid[row][col] = None;
+                return Some((row, col));
+            }
+        }
+        None
+    }
+
+    /// The board reached by playing `moves` from this one.
+    fn with_moves(&self, moves: &[MoveRecord]) -> Self {
+        let mut board = self.clone();
+        for m in moves {
+            board.play(m.usr_move, m.player);
+        }
+        board
+    }
+
+    /// Yellow always moves first, so the side to move follows from the
+    /// number of discs.
+    fn side_to_move(&self) -> Player {
+        if self.ply().is_multiple_of(2) {
+            Player::Yellow
+        } else {
+            Player::Red
+        }
+    }
+
+    /// Number of discs on the board, i.e. moves played so far.
+    fn ply(&self) -> usize {
+        self.grid
+            .iter()
+            .flatten()
+            .filter(|cell| cell.is_some())
+            .count()
+    }
+
+    /// All columns that can still accept a piece, left to right.
+    fn valid_moves(&self) -> Vec<usize> {
+        (0..7).filter(|&col| self.can_play(col)).collect()
+    }
+
     /// Check if the last move by `player` at (row, col) caused that player to win.
     fn is_winning_move(&self, row: usize, col: usize, player: Player) -> bool {
         // 1) Horizontal check
@@ -193,6 +453,31 @@ impl Board {
         false
     }
 
+    /// If the piece at (row, col) is part of four (or more) in a row for `player`,
+    /// return every cell of that line, ordered from one end to the other.
+    fn winning_line(&self, row: usize, col: usize, player: Player) -> Option<Vec<(usize, usize)>> {
+        // Horizontal, vertical, diagonal (\) and diagonal (/)
+        for (dr, dc) in [(0i32, 1i32), (1, 0), (1, 1), (1, -1)] {
+            let mut line = vec![(row, col)];
+            for sign in [-1, 1] {
+                let (mut r, mut c) = (row as i32 + sign * dr, col as i32 + sign * dc);
+                while (0..6).contains(&r)
+                    && (0..7).contains(&c)
+                    && self.grid[r as usize][c as usize] == Some(player)
+                {
+                    line.push((r as usize, c as usize));
+                    r += sign * dr;
+                    c += sign * dc;
+                }
+            }
+            if line.len() >= 4 {
+                line.sort();
+                return Some(line);
+            }
+        }
+        None
+    }
+
     /// Check if the current player has any *immediate winning moves* available.
     /// Returns (has_immediate_win, immediate_win_positions).
     fn immediate_wins(&self, player: Player) -> (bool, Vec<(usize, usize)>) {
@@ -220,29 +505,80 @@ struct MoveRecord {
     has_immediate_win: bool, // Whether the current position had at least one winning move
     immediate_win_positions: Vec<(usize, usize)>, // All winning row/col positions
     player: Player,          // Which player made the move
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    policy: Option<String>, // Which policy actually chose the move, when recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    threats: Option<ThreatAnalysis>, // Threats of both players before the move, when recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    annotation: Option<MoveAnnotation>, // Search values around the move, when annotated
 }
 
-fn random_connect4_match<R: Rng + ?Sized>(rng: &mut R) -> Vec<MoveRecord> {
-    let mut board = Board::new();
+#[cfg(test)]
+impl MoveRecord {
+    /// A move without recorded wins, policy or analysis, for tests.
+    fn new(usr_move: usize, player: Player) -> Self {
+        Self {
+            usr_move,
+            has_immediate_win: false,
+            immediate_win_positions: Vec::new(),
+            player,
+            policy: None,
+            threats: None,
+            annotation: None,
+        }
+    }
+
+    /// The moves of `cols` played in turn, Yellow first.
+    fn alternating(cols: &[usize]) -> Vec<Self> {
+        cols.iter()
+            .enumerate()
+            .map(|(i, &usr_move)| {
+                let player = if i % 2 == 0 {
+                    Player::Yellow
+                } else {
+                    Player::Red
+                };
+                Self::new(usr_move, player)
+            })
+            .collect()
+    }
+}
+
+/// Play one match from `start`, with `yellow` and `red` choosing the moves
+/// for each side once the columns in `opening` have been played.
+fn connect4_match(
+    yellow: &dyn Agent,
+    red: &dyn Agent,
+    start: &Board,
+    opening: &[usize],
+    rng: &mut dyn RngCore,
+) -> Vec<MoveRecord> {
+    let mut board = start.clone();
     let mut moves: Vec<MoveRecord> = Vec::new();
 
-    let mut current_player = Player::Yellow;
+    let mut current_player = start.side_to_move();
 
     loop {
         // Check if the current player has any *immediate* winning moves
         let (has_immediate_win, immediate_win_positions) = board.immediate_wins(current_player);
 
         // Collect valid columns
-        let valid_cols: Vec<usize> = (0..7).filter(|&col| board.can_play(col)).collect();
+        let valid_cols = board.valid_moves();
 
         // If no valid moves remain (board full or no columns available), end the match
         if valid_cols.is_empty() {
             break;
         }
 
-        // Pick a random valid column
-        let chosen_index = rng.random_range(0..valid_cols.len());
-        let col = valid_cols[chosen_index];
+        // Let the current side's agent pick a column
+        let agent = match current_player {
+            Player::Yellow => yellow,
+            Player::Red => red,
+        };
+        let (col, policy) = match opening.get(moves.len()) {
+            Some(&col) => (col, "book".to_string()),
+            None => agent.choose_with_policy(&board, current_player, rng),
+        };
 
         // Place the piece
         let drop_pos = board.play(col, current_player).unwrap();
@@ -253,6 +589,9 @@ fn random_connect4_match<R: Rng + ?Sized>(rng: &mut R) -> Vec<MoveRecord> {
             has_immediate_win,
             immediate_win_positions,
             player: current_player,
+            policy: Some(policy),
+            threats: None,
+            annotation: None,
         });
 
         // Check if this move won the game
@@ -262,73 +601,166 @@ fn random_connect4_match<R: Rng + ?Sized>(rng: &mut R) -> Vec<MoveRecord> {
         }
 
         // Switch players
-        current_player = match current_player {
-            Player::Yellow => Player::Red,
-            Player::Red => Player::Yellow,
-        };
+        current_player = current_player.opponent();
     }
 
     moves
 }
 
-/// Pretty-print the board in its current state.
-/// Using Unicode circles for demonstration.
-fn print_board(board: &Board) {
-    for row in 0..6 {
-        print!("|");
-        for col in 0..7 {
-            match board.grid[row][col] {
-                Some(Player::Yellow) => print!("🟡"),
-                Some(Player::Red) => print!("🔴"),
-                None => print!("⚪"), // empty
-            }
-            print!("|");
-        }
-        println!();
-    }
-    println!("-----------------------------");
+/// Pretty-print the board in its current state using `renderer`.
+fn print_board(board: &Board, renderer: &Renderer) {
+    print!("{}", renderer.render(board));
 }
 
 /// Given a sequence of moves, reconstruct and print the board after each move.
-fn print_match_moves(moves: &[MoveRecord]) {
-    let mut board = Board::new();
+fn print_match_moves(start: &Board, moves: &[MoveRecord], renderer: &Renderer) {
+    let mut board = start.clone();
+    if board.ply() > 0 {
+        println!("=== Start position ===");
+        print_board(&board, renderer);
+        println!();
+    }
     for (i, m) in moves.iter().enumerate() {
-        // Re-play the move on an empty board
+        // Re-play the move on the start board
         let col = m.usr_move;
         board.play(col, m.player);
 
+        let policy = m
+            .policy
+            .as_ref()
+            .map_or(String::new(), |p| format!(" via {}", p));
         println!(
-            "=== Move #{} by {:?} (has_immediate_win={}, positions={:?}) ===",
-            i, m.player, m.has_immediate_win, m.immediate_win_positions
+            "=== Move #{} by {:?}{} (has_immediate_win={}, positions={:?}) ===",
+            i, m.player, policy, m.has_immediate_win, m.immediate_win_positions
         );
-        print_board(&board);
+        if let Some(threats) = &m.threats {
+            println!("{}", analysis::describe(threats));
+        }
+        if let Some(annotation) = &m.annotation {
+            println!("{}", annotate::describe(annotation));
+        }
+        print_board(&board, renderer);
         println!();
     }
 }
 
 fn print_help() {
     println!("Connect-4 Match Generator");
-    println!("");
+    println!();
     println!("USAGE:");
     println!("    connect-4-gen command [OPTIONS]");
-    println!("");
+    println!();
     println!("COMMANDS:");
     println!("    gen   Default mode to generate matches");
     println!("    parse Parse an already generated file, and print a given board");
+    println!("    play  Play a game against an agent in the terminal");
+    println!("    view  Step through the matches of a file in a full-screen viewer");
+    println!("    report Write a self-contained HTML report with statistics and a replayer");
+    println!("    book  Build an opening book from the first plies of generated matches");
+    println!("    tournament Play agents against each other and rate them with Elo");
+    println!("    annotate Add search values, blunder flags and accuracy to every move of a file");
+    println!("    query Replay the matches of a SQLite database that pass --outcome, --min-plies, --max-plies and --where filters");
+    println!("    perft Count the move sequences of exactly --depth plies from a position, per first move");
+    println!("    validate Verify a file's checksum and check that every match replays legally");
     println!("OPTIONS:");
     println!("    -h,   --help                     Show this help message");
     println!("    -n,   --num-matches <NUM>        Number of matches to simulate (default: 1000)");
-    println!("    -f,   --format <FORMAT>          Output format: json, jsonlite, compact (default: jsonlite)");
+    println!("    -f,   --format <FORMAT>          Output format: json, jsonlite, compact, parquet, sqlite, csv, msgpack, cbor (default: jsonlite)");
     println!("    -w,   --store-immediate-wins     Store immediate win statistics (default: true)");
     println!("    -o,   --output <FILE>            Output file (default: matches.json or matches_lite.json)");
+    println!("          --compress <CODEC>         Compress the output file: gzip, zstd (implied by a .gz or .zst output file; not for parquet or sqlite)");
+    println!("          --compression-level <N>    Compression level: 0-9 for gzip (default: 6), 1-22 for zstd (default: 3)");
+    println!("          --shard-size <N>           Split the output into shards of N matches, listed in a manifest");
+    println!("          --num-shards <K>           Split the output into K shards of about equal size, listed in a manifest");
+    println!("          --seed <N>                 Seed the generated matches or tournament games, for reproducible results (default: random)");
+    println!("          --resume                   Continue an interrupted generation run with the same output file");
+    println!("          --append                   Add the generated matches to the existing output file, with ids after its last");
+    println!("          --checksum                 Write a SHA-256 sidecar (<output>.sha256) next to each output file");
+    println!("    -q,   --quiet                    Print nothing but errors while generating");
+    println!("          --progress-json            Print generation progress as JSON lines on stdout, and nothing else there");
+    println!("          --threads <N>              Worker threads for generation and every other parallel command (default: one per CPU)");
+    println!("          --max-memory <SIZE>        Soft memory limit for generation, e.g. 512M or 2G, that sizes its batches and shards");
+    println!("          --time-limit <DURATION>    Stop generating after e.g. 90s, 30m or 2h and save what was generated");
     println!("    -i,   --interactive              Run in interactive mode");
-    println!("    -in,  --input <FILE>             Parses an already generated file (Mandatory field in parse mode)");
+    println!("    -in,  --input <FILE>             Parses an already generated file, shard manifest or glob (Mandatory field in parse mode)");
     println!("    -id,  --id <ID>                  THe ID of the match to show (Mandatory field in parse mode)");
-    println!("");
+    println!("    -a,   --agent <AGENT>            Opponent in play mode: random, tactical, tactical-center (default: random)");
+    println!("                                     Wrap any agent with eps:<E>:<AGENT>, softmax:<T>:<AGENT> or opening:<K>:<AGENT>");
+    println!("                                     or consult an opening book first with book:<FILE>:<AGENT>");
+    println!("          --yellow <AGENT>           Agent playing Yellow when generating (default: random)");
+    println!(
+        "          --red <AGENT>              Agent playing Red when generating (default: random)"
+    );
+    println!("          --first <WHO>              Who moves first in play mode: human, ai (default: human)");
+    println!("    -r,   --render <STYLE>           Board style in parse, play and view modes: ascii, ansi, emoji (default: emoji)");
+    println!(
+        "          --coords                   Label board columns 1-7 and rows 1-6 (bottom to top)"
+    );
+    println!("          --image <FILE>             In parse mode, save the match as an image instead (.svg or .png)");
+    println!("          --ply <K>                  Board to draw with --image: after K moves (default: final position)");
+    println!(
+        "          --filmstrip                With --image, draw every ply of the match in a grid"
+    );
+    println!("          --gif <FILE>               In parse mode, save an animated replay of the match as a GIF");
+    println!(
+        "          --frame-delay <MS>         Time each move is shown in the GIF (default: 500)"
+    );
+    println!("          --depth <N>                Plies stored per match in book mode, or counted in perft mode (default: 8)");
+    println!("          --book <FILE>              Start generated matches from a line sampled from this opening book");
+    println!("          --book-sampling <MODE>     How book moves are sampled: uniform, weighted (default: weighted)");
+    println!("          --start-positions <FILE>   Continue matches from positions listed one per line (\"4453\" or a 42-cell grid)");
+    println!("          --per-position <N>         Play N matches from every start position instead of sampling one per match");
+    println!("          --position <POS>           Start generated matches, the game in play mode or perft from this position");
+    println!("          --threats                  Record both players' threat counts before every generated move");
+    println!("          --search-depth <N>         Plies searched per position in annotate mode (default: 8)");
+    println!(
+        "          --agents <A,B,...>         Comma-separated agents to play in tournament mode"
+    );
+    println!("          --schedule <KIND>          Tournament pairings: round-robin, gauntlet (first agent vs the rest) (default: round-robin)");
+    println!("          --games <N>                Games per pairing in tournament mode, colors alternating (default: 100)");
+    println!("          --outcome <WHO>            In query mode, keep matches won by yellow or red, or drawn (draw)");
+    println!(
+        "          --min-plies <N>            In query mode, keep matches of at least N moves"
+    );
+    println!("          --max-plies <N>            In query mode, keep matches of at most N moves");
+    println!("          --where <SQL>              In query mode, keep matches satisfying this SQL condition on the matches table");
+    println!("          --limit <N>                Matches replayed in query mode (default: 10)");
+    println!();
     println!("EXAMPLES:");
     println!("    connect-4-gen -n 5000 -f json -o my_matches.json");
+    println!("    connect-4-gen -n 100000 -f parquet -o games.parquet");
+    println!("    connect-4-gen -n 5000 --yellow tactical --red random");
+    println!("    connect-4-gen -n 5000 --yellow opening:4:eps:0.1:tactical --red softmax:5:tactical-center");
     println!("    connect-4-gen --interactive");
-    println!("    connect-4-gen parse --in matches.json --id 37")
+    println!("    connect-4-gen parse --in matches.json --id 37");
+    println!("    connect-4-gen parse --input matches.json --id 37 --render ascii --coords");
+    println!("    connect-4-gen parse --input matches.json --id 37 --image board.png --ply 12");
+    println!(
+        "    connect-4-gen parse --input matches.json --id 37 --gif match.gif --frame-delay 300"
+    );
+    println!("    connect-4-gen play --first ai -o human_games.json");
+    println!("    connect-4-gen play --position 4453 --render ascii");
+    println!("    connect-4-gen view --input matches.json --id 37");
+    println!("    connect-4-gen report --input matches.json -o report.html");
+    println!("    connect-4-gen book --input strong.json --depth 8 -o book.bin");
+    println!("    connect-4-gen annotate --input matches.json --search-depth 10 -o annotated.json");
+    println!("    connect-4-gen -n 100000 -f sqlite -o matches.db");
+    println!("    connect-4-gen -n 1000 -f csv -o moves.csv");
+    println!("    connect-4-gen -n 100000 -f json -o matches.json.zst --compression-level 19");
+    println!("    connect-4-gen -n 1000000 -o matches.json.zst --num-shards 16");
+    println!("    connect-4-gen report --input 'matches-*-of-00016.json.zst'");
+    println!("    connect-4-gen -n 1000000 -o matches.json.zst --num-shards 16 --resume");
+    println!("    connect-4-gen -n 5000 -f sqlite -o matches.db --append");
+    println!("    connect-4-gen -n 5000 -o matches.json --checksum");
+    println!("    connect-4-gen validate --input matches.json");
+    println!("    connect-4-gen -n 1000000 -o matches.json.zst --progress-json");
+    println!("    connect-4-gen -n 1000000 --shard-size 100000 --threads 4 --max-memory 2G --time-limit 1h");
+    println!("    connect-4-gen -n 5000 -f msgpack && connect-4-gen parse --input matches.msgpack --id 3");
+    println!("    connect-4-gen query --input matches.db --outcome red --max-plies 14 --limit 3");
+    println!("    connect-4-gen perft --depth 9 --position 4453");
+    println!("    connect-4-gen tournament --agents random,tactical,eps:0.1:tactical --games 200 -o standings.json");
+    println!("    connect-4-gen --start-positions endgames.txt --per-position 100 --yellow tactical --red tactical");
+    println!("    connect-4-gen -n 5000 --book book.bin --book-sampling uniform --yellow book:book.bin:tactical")
 }
 
 fn run_interactive_mode() -> AppConfig {
@@ -356,6 +788,11 @@ fn run_interactive_mode() -> AppConfig {
     println!("  - json: Full JSON output including all move details");
     println!("  - jsonlite: Condensed JSON output (default)");
     println!("  - compact: Binary/compact representation");
+    println!("  - parquet: Columnar moves and matches tables for DuckDB, Polars, etc.");
+    println!("  - sqlite: SQLite database with matches and moves tables");
+    println!("  - csv: One row per move, for spreadsheets");
+    println!("  - msgpack: MessagePack encoding of the full JSON data");
+    println!("  - cbor: CBOR encoding of the full JSON data");
     print!("Choose output format [jsonlite]: ");
     io::stdout().flush().unwrap();
 
@@ -378,10 +815,8 @@ fn run_interactive_mode() -> AppConfig {
     input.clear();
     io::stdin().read_line(&mut input).unwrap();
     input = input.trim().to_lowercase();
-    if !input.is_empty() {
-        if input == "n" || input == "no" {
-            config.store_immediate_wins = false;
-        }
+    if input == "n" || input == "no" {
+        config.store_immediate_wins = false;
     }
 
     // Collect output file
@@ -425,6 +860,33 @@ fn parse_cli_args() -> AppConfig {
             "gen" => {
                 config.mode = ToolMode::Generation;
             }
+            "play" => {
+                config.mode = ToolMode::Playing;
+            }
+            "view" => {
+                config.mode = ToolMode::Viewing;
+            }
+            "report" => {
+                config.mode = ToolMode::Reporting;
+            }
+            "book" => {
+                config.mode = ToolMode::BookBuilding;
+            }
+            "tournament" => {
+                config.mode = ToolMode::Tournament;
+            }
+            "annotate" => {
+                config.mode = ToolMode::Annotating;
+            }
+            "perft" => {
+                config.mode = ToolMode::Perft;
+            }
+            "query" => {
+                config.mode = ToolMode::Querying;
+            }
+            "validate" => {
+                config.mode = ToolMode::Validating;
+            }
             "-h" | "--help" => {
                 print_help();
                 std::process::exit(0);
@@ -480,6 +942,314 @@ fn parse_cli_args() -> AppConfig {
                     i += 1;
                 }
             }
+            "-a" | "--agent" => {
+                if i + 1 < args.len() {
+                    config.agent = args[i + 1].clone();
+                    i += 1;
+                }
+            }
+            "--yellow" => {
+                if i + 1 < args.len() {
+                    config.yellow_agent = args[i + 1].clone();
+                    i += 1;
+                }
+            }
+            "--red" => {
+                if i + 1 < args.len() {
+                    config.red_agent = args[i + 1].clone();
+                    i += 1;
+                }
+            }
+            "--first" => {
+                if i + 1 < args.len() {
+                    match args[i + 1].to_lowercase().as_str() {
+                        "human" => config.human_first = true,
+                        "ai" | "agent" => config.human_first = false,
+                        _ => {
+                            eprintln!("Error: Invalid value for first (must be human/ai)");
+                            std::process::exit(1);
+                        }
+                    }
+                    i += 1;
+                }
+            }
+            "-r" | "--render" => {
+                if i + 1 < args.len() {
+                    match args[i + 1].parse() {
+                        Ok(style) => config.render = style,
+                        Err(_) => {
+                            eprintln!("Error: Invalid render style");
+                            std::process::exit(1);
+                        }
+                    }
+                    i += 1;
+                }
+            }
+            "--coords" => {
+                config.coordinates = true;
+            }
+            "--image" => {
+                if i + 1 < args.len() {
+                    config.image = Some(PathBuf::from(&args[i + 1]));
+                    i += 1;
+                }
+            }
+            "--ply" => {
+                if i + 1 < args.len() {
+                    if let Ok(ply) = args[i + 1].parse() {
+                        config.ply = Some(ply);
+                    } else {
+                        eprintln!("Error: Invalid ply");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--filmstrip" => {
+                config.filmstrip = true;
+            }
+            "--gif" => {
+                if i + 1 < args.len() {
+                    config.gif = Some(PathBuf::from(&args[i + 1]));
+                    i += 1;
+                }
+            }
+            "--frame-delay" => {
+                if i + 1 < args.len() {
+                    if let Ok(ms) = args[i + 1].parse() {
+                        config.frame_delay_ms = ms;
+                    } else {
+                        eprintln!("Error: Invalid frame delay");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--depth" => {
+                if i + 1 < args.len() {
+                    if let Ok(depth) = args[i + 1].parse() {
+                        config.depth = depth;
+                    } else {
+                        eprintln!("Error: Invalid depth");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--book" => {
+                if i + 1 < args.len() {
+                    config.book = Some(PathBuf::from(&args[i + 1]));
+                    i += 1;
+                }
+            }
+            "--book-sampling" => {
+                if i + 1 < args.len() {
+                    config.book_sampling = args[i + 1].parse().unwrap_or_else(|e| {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    });
+                    i += 1;
+                }
+            }
+            "--start-positions" => {
+                if i + 1 < args.len() {
+                    config.start_positions = Some(PathBuf::from(&args[i + 1]));
+                    i += 1;
+                }
+            }
+            "--per-position" => {
+                if i + 1 < args.len() {
+                    if let Ok(n) = args[i + 1].parse() {
+                        config.per_position = Some(n);
+                    } else {
+                        eprintln!("Error: Invalid number of matches per position");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--position" => {
+                if i + 1 < args.len() {
+                    config.position = Some(args[i + 1].clone());
+                    i += 1;
+                }
+            }
+            "--threats" => {
+                config.record_threats = true;
+            }
+            "--seed" => {
+                if i + 1 < args.len() {
+                    if let Ok(seed) = args[i + 1].parse() {
+                        config.seed = Some(seed);
+                    } else {
+                        eprintln!("Error: Invalid seed");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--resume" => {
+                config.resume = true;
+            }
+            "--append" => {
+                config.append = true;
+            }
+            "--checksum" => {
+                config.checksum = true;
+            }
+            "-q" | "--quiet" => {
+                config.quiet = true;
+            }
+            "--progress-json" => {
+                config.progress_json = true;
+            }
+            "--threads" => {
+                if i + 1 < args.len() {
+                    if let Ok(threads) = args[i + 1].parse() {
+                        config.threads = Some(threads);
+                    } else {
+                        eprintln!("Error: Invalid number of threads");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--max-memory" => {
+                if i + 1 < args.len() {
+                    config.max_memory =
+                        Some(resources::parse_size(&args[i + 1]).unwrap_or_else(|e| {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(1);
+                        }));
+                    i += 1;
+                }
+            }
+            "--time-limit" => {
+                if i + 1 < args.len() {
+                    config.time_limit =
+                        Some(resources::parse_duration(&args[i + 1]).unwrap_or_else(|e| {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(1);
+                        }));
+                    i += 1;
+                }
+            }
+            "--search-depth" => {
+                if i + 1 < args.len() {
+                    if let Ok(depth) = args[i + 1].parse() {
+                        config.search_depth = depth;
+                    } else {
+                        eprintln!("Error: Invalid search depth");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--agents" => {
+                if i + 1 < args.len() {
+                    config.tournament_agents = args[i + 1]
+                        .split(',')
+                        .map(|spec| spec.trim().to_string())
+                        .collect();
+                    i += 1;
+                }
+            }
+            "--schedule" => {
+                if i + 1 < args.len() {
+                    config.schedule = args[i + 1].parse().unwrap_or_else(|e| {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    });
+                    i += 1;
+                }
+            }
+            "--games" => {
+                if i + 1 < args.len() {
+                    if let Ok(games) = args[i + 1].parse() {
+                        config.games_per_pairing = games;
+                    } else {
+                        eprintln!("Error: Invalid number of games per pairing");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--outcome" => {
+                if i + 1 < args.len() {
+                    config.outcome = Some(args[i + 1].parse().unwrap_or_else(|e| {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }));
+                    i += 1;
+                }
+            }
+            "--min-plies" | "--max-plies" => {
+                if i + 1 < args.len() {
+                    if let Ok(plies) = args[i + 1].parse() {
+                        if args[i] == "--min-plies" {
+                            config.min_plies = Some(plies);
+                        } else {
+                            config.max_plies = Some(plies);
+                        }
+                    } else {
+                        eprintln!("Error: Invalid number of plies");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--where" => {
+                if i + 1 < args.len() {
+                    config.condition = Some(args[i + 1].clone());
+                    i += 1;
+                }
+            }
+            "--limit" => {
+                if i + 1 < args.len() {
+                    if let Ok(limit) = args[i + 1].parse() {
+                        config.limit = limit;
+                    } else {
+                        eprintln!("Error: Invalid limit");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--compress" => {
+                if i + 1 < args.len() {
+                    config.compress = Some(args[i + 1].parse().unwrap_or_else(|e| {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    }));
+                    i += 1;
+                }
+            }
+            "--compression-level" => {
+                if i + 1 < args.len() {
+                    if let Ok(level) = args[i + 1].parse() {
+                        config.compression_level = Some(level);
+                    } else {
+                        eprintln!("Error: Invalid compression level");
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
+            "--shard-size" | "--num-shards" => {
+                if i + 1 < args.len() {
+                    if let Ok(n) = args[i + 1].parse() {
+                        if args[i] == "--shard-size" {
+                            config.shard_size = Some(n);
+                        } else {
+                            config.num_shards = Some(n);
+                        }
+                    } else {
+                        eprintln!("Error: Invalid {} value", args[i]);
+                        std::process::exit(1);
+                    }
+                    i += 1;
+                }
+            }
             "-id" | "--id" => {
                 if i + 1 < args.len() {
                     config.id = Some(usize::from_str(&args[i + 1]).unwrap());
@@ -501,16 +1271,67 @@ fn parse_cli_args() -> AppConfig {
 fn is_valid_config(config: &AppConfig) -> bool {
     match config.mode {
         ToolMode::Parsing => {
-            if let None = config.input_file {
+            if config.input_file.is_none() {
                 panic!("Input file is mandatory in parse mode, add it with -in=path.json");
             }
 
-            if let None = config.id {
+            if config.id.is_none() {
                 panic!("ID is mandatory in parse mode, add it with -id=<ID>");
             }
 
             true
         }
+        ToolMode::Viewing => {
+            if config.input_file.is_none() {
+                panic!("Input file is mandatory in view mode, add it with -in=path.json");
+            }
+
+            true
+        }
+        ToolMode::Reporting => {
+            if config.input_file.is_none() {
+                panic!("Input file is mandatory in report mode, add it with -in=path.json");
+            }
+
+            true
+        }
+        ToolMode::BookBuilding => {
+            if config.input_file.is_none() {
+                panic!("Input file is mandatory in book mode, add it with -in=path.json");
+            }
+
+            true
+        }
+        ToolMode::Annotating => {
+            if config.input_file.is_none() {
+                panic!("Input file is mandatory in annotate mode, add it with -in=path.json");
+            }
+
+            true
+        }
+        ToolMode::Querying => {
+            if config.input_file.is_none() {
+                panic!("Input file is mandatory in query mode, add it with -in=path.db");
+            }
+
+            true
+        }
+        ToolMode::Validating => {
+            if config.input_file.is_none() {
+                panic!("Input file is mandatory in validate mode, add it with -in=path.json");
+            }
+
+            true
+        }
+        ToolMode::Tournament => {
+            if config.tournament_agents.len() < 2 {
+                panic!(
+                    "At least two agents are needed in tournament mode, add them with --agents=a,b"
+                );
+            }
+
+            true
+        }
         _ => true,
     }
 }
@@ -520,95 +1341,622 @@ fn get_default_output_file(format: &OutputFormat) -> PathBuf {
         OutputFormat::Json => PathBuf::from("matches.json"),
         OutputFormat::JsonLite => PathBuf::from("matches_lite.json"),
         OutputFormat::Compact => PathBuf::from("matches.bin"),
+        OutputFormat::Parquet => PathBuf::from("moves.parquet"),
+        OutputFormat::Sqlite => PathBuf::from("matches.db"),
+        OutputFormat::Csv => PathBuf::from("moves.csv"),
+        OutputFormat::MessagePack => PathBuf::from("matches.msgpack"),
+        OutputFormat::Cbor => PathBuf::from("matches.cbor"),
+    }
+}
+
+/// A copy of `matches` with the immediate win positions left out.
+fn without_immediate_wins(matches: &[Match]) -> Vec<Match> {
+    matches
+        .iter()
+        .map(|match_moves| {
+            let mut filtered = match_moves.clone();
+            for move_record in &mut filtered.moves {
+                move_record.immediate_win_positions = Vec::new();
+            }
+            filtered
+        })
+        .collect()
+}
+
+/// Serialize `matches` to `path` according to `format`, compressed with
+/// `compression` if given, and with a checksum sidecar if `checksum` is set.
+/// Existing sidecars are kept up to date either way.
+fn save_matches(
+    matches: &[Match],
+    format: OutputFormat,
+    path: &Path,
+    store_immediate_wins: bool,
+    compression: Option<Compression>,
+    checksum: bool,
+) -> Result<(), String> {
+    write_matches(matches, format, path, store_immediate_wins, compression)?;
+    let mut written = vec![path.to_path_buf()];
+    if format == OutputFormat::Parquet {
+        written.push(columnar::matches_table_path(path));
+    }
+    for path in written {
+        if checksum || integrity::sidecar_path(&path).exists() {
+            integrity::write_sidecar(&path)?;
+        }
     }
+    Ok(())
+}
+
+/// Write the output file(s) of `save_matches`, each atomically.
+fn write_matches(
+    matches: &[Match],
+    format: OutputFormat,
+    path: &Path,
+    store_immediate_wins: bool,
+    compression: Option<Compression>,
+) -> Result<(), String> {
+    format.check_compression(compression)?;
+    match format {
+        OutputFormat::Compact => {
+            // For a compact format, we could use a binary serialization format like bincode
+            // This is a placeholder - implement actual compact format if needed
+            return Err("Compact format not yet implemented".to_string());
+        }
+        OutputFormat::Sqlite => {
+            return database::save_database(matches, path, store_immediate_wins);
+        }
+        OutputFormat::Parquet => {
+            return columnar::save_parquet(matches, path, store_immediate_wins);
+        }
+        _ => {}
+    }
+
+    let mut writer = compress::create(path, compression)?;
+    if format == OutputFormat::Csv {
+        csv::write_csv(matches, &mut writer, store_immediate_wins)
+            .map_err(|e| format!("Failed to write CSV output: {}", e))?;
+    } else {
+        // If not storing immediate wins, filter them out
+        let filtered_matches = (!store_immediate_wins).then(|| without_immediate_wins(matches));
+        let matches = filtered_matches.as_deref().unwrap_or(matches);
+        match format {
+            OutputFormat::MessagePack => interchange::write_msgpack(matches, &mut writer)?,
+            OutputFormat::Cbor => interchange::write_cbor(matches, &mut writer)?,
+            _ => serde_json::to_writer(&mut writer, matches)
+                .map_err(|e| format!("Failed to write JSON output: {}", e))?,
+        }
+    }
+    writer
+        .finish()
+        .map_err(|e| format!("Failed to write output file: {}", e))
+}
+
+/// Which format a match file was written in, given its first (decompressed)
+/// bytes: SQLite and CBOR by their magic bytes, then MessagePack and JSON by
+/// extension or, failing that, by how the list of matches starts.
+fn detect_input_format(path: &Path, header: &[u8]) -> OutputFormat {
+    if header.starts_with(database::MAGIC) {
+        return OutputFormat::Sqlite;
+    }
+    if header.starts_with(&interchange::CBOR_MAGIC) {
+        return OutputFormat::Cbor;
+    }
+    let path = compress::strip_extension(path);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension.to_lowercase().as_str() {
+        "msgpack" | "mpk" | "mp" => OutputFormat::MessagePack,
+        "cbor" => OutputFormat::Cbor,
+        "json" => OutputFormat::Json,
+        _ => match header.first() {
+            Some(&byte) if interchange::is_msgpack_array(byte) => OutputFormat::MessagePack,
+            _ => OutputFormat::Json,
+        },
+    }
+}
+
+/// Read back a JSON, MessagePack or CBOR file or a SQLite database written
+/// by `save_matches`, detecting which it is and any gzip or zstd compression.
+/// A shard manifest or a glob pattern reads every file it names as one dataset.
+fn load_matches(path: &Path) -> Result<Vec<Match>, String> {
+    if shard::is_manifest(path) || shard::is_glob(path) {
+        return shard::load_dataset(path);
+    }
+    if database::is_database(path) {
+        return database::load_matches(path);
+    }
+    let mut reader =
+        compress::open(path).map_err(|e| format!("Failed to open input file: {}", e))?;
+    let header = reader
+        .fill_buf()
+        .map_err(|e| format!("Failed to read input file: {}", e))?;
+    let matches: Vec<Match> = match detect_input_format(path, header) {
+        OutputFormat::Sqlite => {
+            return Err("Compressed SQLite databases must be decompressed first".to_string())
+        }
+        OutputFormat::MessagePack => interchange::read_msgpack(reader)?,
+        OutputFormat::Cbor => interchange::read_cbor(reader)?,
+        _ => serde_json::from_reader(reader).map_err(|e| format!("Failed to parse JSON: {}", e))?,
+    };
+    check_starts(&matches)?;
+    Ok(matches)
+}
+
+/// Check that every match starts from a valid, unfinished position, so that
+/// `Match::start_board` can rely on it.
+fn check_starts(matches: &[Match]) -> Result<(), String> {
+    for m in matches {
+        if let Some(start) = &m.start {
+            position::normalize_position(start)
+                .map_err(|e| format!("Invalid start of match {}: {}", m.id, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse a position given on the command line, exiting on error.
+fn parse_start(position: &str) -> (String, Board) {
+    position::normalize_position(position).unwrap_or_else(|e| {
+        eprintln!("Error: Invalid position: {}", e);
+        std::process::exit(1);
+    })
 }
 
 fn do_generate(config: AppConfig) {
-    println!("Generating {} matches...", config.num_matches);
-
-    // Generate matches in parallel
-    let all_matches: Vec<Match> = (0..config.num_matches)
-        .into_par_iter()
-        .map(|_i| {
-            // Each thread uses its own RNG instance
-            let mut rng = rand::rng();
-            Match::new(_i + 1, random_connect4_match(&mut rng))
+    let start_time = Instant::now();
+    let parse_side = |spec: &str| {
+        parse_agent(spec).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    };
+    let yellow = parse_side(&config.yellow_agent);
+    let red = parse_side(&config.red_agent);
+    let book = config.book.as_ref().map(|path| {
+        OpeningBook::load(path).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         })
-        .collect();
+    });
+
+    let positions = match (&config.position, &config.start_positions) {
+        (Some(position), _) => Some(vec![parse_start(position)]),
+        (None, Some(path)) => Some(position::load_start_positions(path).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })),
+        (None, None) => None,
+    };
+    // With --per-position every start gets the same number of matches,
+    // otherwise each match samples its start
+    let num_matches = match (&positions, config.per_position) {
+        (Some(positions), Some(per_position)) => positions.len() * per_position,
+        _ => config.num_matches,
+    };
+
+    // Check the shard flags before spending time on generation
+    let mut sharded = config.shard_size.is_some() || config.num_shards.is_some();
+    let mut units = shard::shard_ranges(num_matches, config.shard_size, config.num_shards)
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    let limits =
+        Limits::new(config.max_memory, config.time_limit, start_time).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    if sharded && config.append {
+        eprintln!("Error: --append cannot be combined with sharding");
+        std::process::exit(1);
+    }
+    if config.append && !config.output_format.is_readable() {
+        eprintln!("Error: --append needs an output format that can be read back: json, jsonlite, msgpack, cbor or sqlite");
+        std::process::exit(1);
+    }
+
+    let settings = run_settings(&config);
 
     // Determine output file
-    let output_path = config
-        .output_file
-        .unwrap_or_else(|| get_default_output_file(&config.output_format));
-
-    // Process and save according to format
-    match config.output_format {
-        OutputFormat::Json | OutputFormat::JsonLite => {
-            let output_file =
-                std::fs::File::create(&output_path).expect("Failed to create output file");
-
-            // If not storing immediate wins, filter them out
-            if !config.store_immediate_wins {
-                // Create a version with filtered data
-                let filtered_matches: Vec<Vec<MoveRecord>> = all_matches
-                    .iter()
-                    .map(|match_moves| {
-                        match_moves
-                            .moves
-                            .iter()
-                            .map(|move_record| {
-                                let mut filtered = move_record.clone();
-                                filtered.immediate_win_positions = Vec::new();
-                                filtered
-                            })
-                            .collect()
-                    })
-                    .collect();
-
-                serde_json::to_writer(output_file, &filtered_matches)
-                    .expect("Failed to write JSON output");
+    let output_path = config.output_file.unwrap_or_else(|| {
+        let path = get_default_output_file(&config.output_format);
+        match config.compress {
+            Some(codec) => compress::with_extension(path, codec),
+            None => path,
+        }
+    });
+    let compression =
+        Compression::for_output(config.compress, config.compression_level, &output_path);
+    if let Err(e) = config.output_format.check_compression(compression) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+
+    // Appended matches are saved along with the existing ones
+    let mut existing = if config.append {
+        load_matches(&output_path).unwrap_or_else(|e| {
+            eprintln!("Error: Cannot append to {}: {}", output_path.display(), e);
+            std::process::exit(1);
+        })
+    } else {
+        Vec::new()
+    };
+    let mode = if config.progress_json {
+        ProgressMode::Json
+    } else if config.quiet {
+        ProgressMode::Quiet
+    } else {
+        ProgressMode::Human
+    };
+    if let Some(max_memory) = config.max_memory {
+        // Each output file is held in memory until it is saved, so output
+        // files that would not fit are split into smaller shards
+        let held = existing.len() + units.iter().map(|unit| unit.len()).max().unwrap_or(0);
+        if !resources::fits(max_memory, held) {
+            let shard_size = resources::fitting_shard_size(max_memory);
+            if config.append {
+                if mode != ProgressMode::Quiet {
+                    eprintln!(
+                        "Warning: the {} matches of {} may need more than --max-memory until saved, and an --append run cannot be split into shards",
+                        held,
+                        output_path.display()
+                    );
+                }
             } else {
-                serde_json::to_writer(output_file, &all_matches)
-                    .expect("Failed to write JSON output");
+                units = shard::shard_ranges(num_matches, Some(shard_size), None)
+                    .expect("shards hold at least one match");
+                sharded = true;
+                if mode.announces() {
+                    println!(
+                        "Splitting the output into shards of {} matches to stay under --max-memory",
+                        shard_size
+                    );
+                }
             }
         }
-        OutputFormat::Compact => {
-            // For a compact format, we could use a binary serialization format like bincode
-            // This is a placeholder - implement actual compact format if needed
-            eprintln!("Compact format not yet implemented");
+    }
+
+    let checkpoint = if config.resume {
+        let checkpoint = match Checkpoint::load(&output_path) {
+            Ok(Some(checkpoint)) => checkpoint,
+            Ok(None) => {
+                eprintln!(
+                    "Error: No interrupted run to resume for {}",
+                    output_path.display()
+                );
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if checkpoint.num_matches != num_matches || checkpoint.num_units != units.len() {
+            eprintln!(
+                "Error: The interrupted run generates {} matches in {} files, resume it with the same options",
+                checkpoint.num_matches, checkpoint.num_units
+            );
+            std::process::exit(1);
+        }
+        if checkpoint.append != config.append {
+            let flag = if checkpoint.append { "with" } else { "without" };
+            eprintln!(
+                "Error: The interrupted run was started {} --append, resume it with the same options",
+                flag
+            );
+            std::process::exit(1);
+        }
+        if config.seed.is_some_and(|seed| seed != checkpoint.seed) {
+            eprintln!(
+                "Error: The interrupted run uses --seed {}, resume it with the same options",
+                checkpoint.seed
+            );
+            std::process::exit(1);
+        }
+        if checkpoint.settings != settings {
+            let (flag, value) = checkpoint
+                .settings
+                .iter()
+                .find(|setting| !settings.contains(setting))
+                .cloned()
+                .unwrap_or_default();
+            let used = if value.is_empty() {
+                format!("was started without {}", flag)
+            } else {
+                format!("uses {} {}", flag, value)
+            };
+            eprintln!(
+                "Error: The interrupted run {}, resume it with the same options",
+                used
+            );
             std::process::exit(1);
         }
+        checkpoint
+    } else {
+        let first_id = existing.iter().map(|m| m.id).max().unwrap_or(0) + 1;
+        let seed = config.seed.unwrap_or_else(|| rand::rng().random());
+        Checkpoint::new(
+            seed,
+            first_id,
+            num_matches,
+            config.append,
+            settings,
+            units.len(),
+        )
+    };
+    let (seed, first_id) = (checkpoint.seed, checkpoint.first_id);
+    // An interrupted append may have saved its matches already
+    existing.retain(|m| m.id < first_id);
+
+    // Matches already generated by the run being resumed
+    let initial = units[..checkpoint.units_done]
+        .iter()
+        .map(|unit| unit.len())
+        .sum::<usize>()
+        + checkpoint.journaled;
+    let progress = Progress::new(mode, num_matches, initial);
+
+    if mode.announces() {
+        let action = if config.resume {
+            "Resuming the generation of"
+        } else {
+            "Generating"
+        };
+        println!(
+            "{} {} matches ({} as Yellow vs {} as Red, seed {})...",
+            action,
+            num_matches,
+            yellow.name(),
+            red.name(),
+            seed
+        );
     }
 
-    println!(
-        "Successfully generated {} matches and saved to {}",
-        config.num_matches,
-        output_path.display()
-    );
+    // Generate matches in parallel. `indices` count from the run's first
+    // match, and each match's RNG is seeded from its id, so a resumed run
+    // makes the same matches as an uninterrupted one.
+    let generate = |indices: Range<usize>| -> Vec<Match> {
+        indices
+            .into_par_iter()
+            .map(|index| {
+                let id = first_id + index;
+                let mut rng = match_rng(seed, id);
+                let start = positions
+                    .as_ref()
+                    .map(|positions| match config.per_position {
+                        Some(per_position) => &positions[index / per_position],
+                        None => &positions[rng.random_range(0..positions.len())],
+                    });
+                let start_board = start.map_or_else(Board::new, |(_, board)| board.clone());
+                // Start from a book line so matches don't all re-explore the same openings
+                let opening = book
+                    .as_ref()
+                    .map(|book| book.sample_line(&start_board, config.book_sampling, &mut rng))
+                    .unwrap_or_default();
+                let mut moves = connect4_match(
+                    yellow.as_ref(),
+                    red.as_ref(),
+                    &start_board,
+                    &opening,
+                    &mut rng,
+                );
+                if config.record_threats {
+                    analysis::annotate_threats(&start_board, &mut moves);
+                }
+                progress.record(moves.len());
+                Match {
+                    id,
+                    moves,
+                    start: start.map(|(notation, _)| notation.clone()),
+                    accuracy: None,
+                }
+            })
+            .collect()
+    };
+
+    let save = |matches: &[Match], path: &Path| {
+        save_matches(
+            matches,
+            config.output_format,
+            path,
+            config.store_immediate_wins,
+            compression,
+            config.checksum,
+        )
+    };
+
+    // Sharded runs save one shard at a time so only one is held in memory
+    let save_unit = |index, matches: Vec<Match>| {
+        if sharded {
+            let path = shard::shard_path(&output_path, index, units.len());
+            save(&matches, &path)
+        } else if config.append {
+            existing.extend(matches);
+            save(&existing, &output_path)
+        } else {
+            save(&matches, &output_path)
+        }
+    };
+    let run = || {
+        checkpoint::run(
+            &output_path,
+            checkpoint,
+            &units,
+            limits,
+            generate,
+            save_unit,
+        )
+    };
+    let end = progress.run(run).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        eprintln!("Run the same command with --resume to continue");
+        std::process::exit(1);
+    });
+
+    // The match indices of the output files that were saved
+    let saved = match end {
+        RunEnd::Complete => units.clone(),
+        RunEnd::Stopped {
+            units_done,
+            partial,
+        } => {
+            let mut saved = units[..units_done].to_vec();
+            let start = units[units_done].start;
+            if partial > 0 {
+                saved.push(start..start + partial);
+            }
+            saved
+        }
+    };
+    let generated: usize = saved.iter().map(|unit| unit.len()).sum();
+
+    let destination = if sharded {
+        let shards = saved
+            .iter()
+            .enumerate()
+            .map(|(index, range)| {
+                let path = shard::shard_path(&output_path, index, units.len());
+                shard::ShardInfo::new(&path, first_id + range.start..=first_id + range.end - 1)
+            })
+            .collect::<Result<Vec<_>, _>>();
+        let manifest_path = shard::manifest_path(&output_path);
+        if let Err(e) = shards.and_then(|shards| {
+            shard::Manifest::new(&config.output_format.to_string(), shards).save(&manifest_path)
+        }) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        format!(
+            "in {} shards listed in {}",
+            saved.len(),
+            manifest_path.display()
+        )
+    } else if config.append {
+        format!("and appended them to {}", output_path.display())
+    } else {
+        format!("and saved to {}", output_path.display())
+    };
+
+    if end == RunEnd::Complete {
+        checkpoint::clear(&output_path);
+        if mode.announces() {
+            println!(
+                "Successfully generated {} matches {}",
+                generated, destination
+            );
+        }
+    } else if mode.announces() {
+        // The checkpoint stays, so the run can be finished later
+        println!(
+            "Time limit reached: generated {} of {} matches {}",
+            generated, num_matches, destination
+        );
+        println!("Run the same command with --resume to generate the rest");
+    }
+}
+
+/// The options of a generation run, besides the number of matches, seed and
+/// layout, that decide its matches and how they are saved, as (flag, value).
+/// `--resume` refuses to continue a run with different ones.
+fn run_settings(config: &AppConfig) -> Vec<(String, String)> {
+    let path = |path: &Option<PathBuf>| {
+        path.as_ref()
+            .map_or(String::new(), |path| path.display().to_string())
+    };
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    [
+        ("--yellow", config.yellow_agent.clone()),
+        ("--red", config.red_agent.clone()),
+        ("--book", path(&config.book)),
+        ("--book-sampling", config.book_sampling.to_string()),
+        ("--position", optional(config.position.clone())),
+        ("--start-positions", path(&config.start_positions)),
+        (
+            "--per-position",
+            optional(config.per_position.map(|n| n.to_string())),
+        ),
+        ("--threats", config.record_threats.to_string()),
+        ("--format", config.output_format.to_string()),
+        (
+            "--store-immediate-wins",
+            config.store_immediate_wins.to_string(),
+        ),
+        (
+            "--compress",
+            optional(config.compress.map(|c| c.to_string())),
+        ),
+        (
+            "--compression-level",
+            optional(config.compression_level.map(|l| l.to_string())),
+        ),
+    ]
+    .into_iter()
+    .map(|(flag, value)| (flag.to_string(), value))
+    .collect()
+}
+
+/// The RNG of match `id` in a run with `seed`.
+fn match_rng(seed: u64, id: usize) -> StdRng {
+    StdRng::seed_from_u64(seed ^ (id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
 }
 
 fn do_parse(config: AppConfig) {
     if let Some(input_file) = &config.input_file {
-        let file = std::fs::File::open(input_file).expect("Failed to open input file");
-        let all_matches: Vec<Match> =
-            serde_json::from_reader(file).expect("Failed to parse JSON");
-
+        if let Err(e) = integrity::verify_sidecar(input_file) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
         if let Some(id) = config.id {
-            if id < all_matches.len() {
-                let index = all_matches.iter().position(|match_moves| match_moves.id == id);
-                if let None = index {
-                    eprintln!("Error: Match ID {} not found", id);
+            // A database can look the match up without reading the others
+            let selected = if database::is_database(input_file) {
+                database::load_match(input_file, id)
+            } else if shard::is_manifest(input_file) {
+                shard::load_match(input_file, id)
+            } else {
+                load_matches(input_file)
+                    .map(|all_matches| all_matches.into_iter().find(|m| m.id == id))
+            };
+            let selected = selected.unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            });
+            let Some(selected) = selected else {
+                eprintln!("Error: Match ID {} not found", id);
+                std::process::exit(1);
+            };
+
+            let match_moves = &selected.moves;
+            let start = selected.start_board();
+            if let Some(image_path) = &config.image {
+                if let Err(e) = image::export_match_image(
+                    &start,
+                    match_moves,
+                    image_path,
+                    config.ply,
+                    config.filmstrip,
+                ) {
+                    eprintln!("Error: {}", e);
                     std::process::exit(1);
                 }
-                let match_moves = &all_matches[index.unwrap()].moves;
-                print_match_moves(match_moves);
-            } else {
-                eprintln!(
-                    "Error: Match ID {} is out of range. Total matches: {}",
-                    id,
-                    all_matches.len()
+                println!("Saved match {} image to {}", id, image_path.display());
+            }
+            if let Some(gif_path) = &config.gif {
+                if let Err(e) =
+                    image::export_match_gif(&start, match_moves, gif_path, config.frame_delay_ms)
+                {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+                println!("Saved match {} animation to {}", id, gif_path.display());
+            }
+            if config.image.is_none() && config.gif.is_none() {
+                if let Some(accuracy) = selected.accuracy {
+                    println!("Match {} accuracy: {:.1}%", id, accuracy);
+                }
+                print_match_moves(
+                    &start,
+                    match_moves,
+                    &Renderer::new(config.render, config.coordinates),
                 );
-                std::process::exit(1);
             }
         } else {
             eprintln!("Error: Match ID is required for parsing mode");
@@ -629,15 +1977,32 @@ fn main() {
         std::process::exit(1);
     }
 
+    if let Some(threads) = config.threads {
+        if let Err(e) = resources::configure_threads(threads) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
     match config.mode {
         ToolMode::Generation => do_generate(config),
         ToolMode::Parsing => do_parse(config),
+        ToolMode::Playing => play::do_play(config),
+        ToolMode::Viewing => tui::do_view(config),
+        ToolMode::Reporting => report::do_report(config),
+        ToolMode::BookBuilding => book::do_build_book(config),
+        ToolMode::Tournament => tournament::do_tournament(config),
+        ToolMode::Annotating => annotate::do_annotate(config),
+        ToolMode::Perft => perft::do_perft(config),
+        ToolMode::Querying => database::do_query(config),
+        ToolMode::Validating => integrity::do_validate(config),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use agent::{RandomAgent, TacticalAgent};
     use rand::rngs::mock::StepRng;
 
     #[test]
@@ -680,6 +2045,17 @@ mod tests {
         assert_eq!(board.grid[4][3], Some(Player::Red));
     }
 
+    #[test]
+    fn test_undo() {
+        let mut board: Board = "33".parse().unwrap();
+
+        assert_eq!(board.undo(2), Some((4, 2)));
+        assert_eq!(board.grid[4][2], None);
+        assert_eq!(board.grid[5][2], Some(Player::Yellow));
+        assert_eq!(board.undo(2), Some((5, 2)));
+        assert_eq!(board.undo(2), None);
+    }
+
     #[test]
     fn test_horizontal_win() {
         let mut board = Board::new();
@@ -758,6 +2134,63 @@ mod tests {
         assert!(board.is_winning_move(pos.0, pos.1, Player::Red));
     }
 
+    #[test]
+    fn test_winning_line() {
+        let mut board = Board::new();
+        board.play(1, Player::Red);
+        board.play(2, Player::Red);
+        board.play(3, Player::Red);
+        board.play(5, Player::Red);
+        assert_eq!(board.winning_line(5, 3, Player::Red), None);
+
+        let pos = board.play(4, Player::Red).unwrap();
+        assert_eq!(
+            board.winning_line(pos.0, pos.1, Player::Red),
+            Some(vec![(5, 1), (5, 2), (5, 3), (5, 4), (5, 5)])
+        );
+    }
+
+    #[test]
+    fn test_with_moves() {
+        let moves: Vec<MoveRecord> = [(3, Player::Yellow), (3, Player::Red), (0, Player::Yellow)]
+            .iter()
+            .map(|&(usr_move, player)| MoveRecord::new(usr_move, player))
+            .collect();
+        let board = Board::new().with_moves(&moves);
+        assert_eq!(board.grid[5][3], Some(Player::Yellow));
+        assert_eq!(board.grid[4][3], Some(Player::Red));
+        assert_eq!(board.grid[5][0], Some(Player::Yellow));
+    }
+
+    #[test]
+    fn test_match_winner() {
+        let mut moves = MoveRecord::alternating(&[0, 0, 1, 1, 2, 2]);
+        assert_eq!(Match::new(1, moves.clone()).winner(), None);
+
+        moves.push(MoveRecord::new(3, Player::Yellow));
+        assert_eq!(Match::new(1, moves).winner(), Some(Player::Yellow));
+        assert_eq!(Match::new(2, Vec::new()).winner(), None);
+
+        // The same win, reached from a start position instead.
+        let from_start = Match {
+            start: Some("112233".to_string()),
+            ..Match::new(3, vec![MoveRecord::new(3, Player::Yellow)])
+        };
+        assert_eq!(from_start.start_board().side_to_move(), Player::Yellow);
+        assert_eq!(from_start.winner(), Some(Player::Yellow));
+    }
+
+    #[test]
+    fn test_match_from_start_position() {
+        let start: Board = "4453".parse().unwrap();
+        let mut rng = rand::rng();
+        let moves = connect4_match(&RandomAgent, &RandomAgent, &start, &[], &mut rng);
+        assert_eq!(moves[0].player, Player::Yellow);
+        let board = start.with_moves(&moves);
+        assert_eq!(board.ply(), 4 + moves.len());
+        assert_eq!(board.grid[4][3], Some(Player::Red));
+    }
+
     #[test]
     fn test_immediate_wins() {
         let mut board = Board::new();
@@ -793,7 +2226,7 @@ mod tests {
     fn test_random_match() {
         // Use a deterministic RNG for testing
         let mut rng = StepRng::new(42, 1);
-        let moves = random_connect4_match(&mut rng);
+        let moves = connect4_match(&RandomAgent, &RandomAgent, &Board::new(), &[], &mut rng);
 
         // Verify match terminates with a win or draw
         let mut board = Board::new();
@@ -817,6 +2250,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_tactical_vs_random_match_is_legal() {
+        let mut rng = StepRng::new(7, 3);
+        let moves = connect4_match(
+            &TacticalAgent::default(),
+            &RandomAgent,
+            &Board::new(),
+            &[],
+            &mut rng,
+        );
+        assert!(!moves.is_empty());
+
+        let mut board = Board::new();
+        for (i, m) in moves.iter().enumerate() {
+            let expected = if i % 2 == 0 {
+                Player::Yellow
+            } else {
+                Player::Red
+            };
+            assert_eq!(m.player, expected);
+            assert!(board.play(m.usr_move, m.player).is_some());
+        }
+    }
+
     #[test]
     fn test_invalid_play() {
         let mut board = Board::new();
@@ -830,4 +2287,18 @@ mod tests {
         let pos = board.play(0, Player::Red);
         assert_eq!(pos, None);
     }
+
+    #[test]
+    fn test_detect_input_format() {
+        let detect = |path: &str, header: &[u8]| detect_input_format(Path::new(path), header);
+        assert_eq!(detect("m.dat", b"SQLite format 3\0"), OutputFormat::Sqlite);
+        assert_eq!(
+            detect("m.json", &interchange::CBOR_MAGIC),
+            OutputFormat::Cbor
+        );
+        assert_eq!(detect("m.msgpack.zst", b"["), OutputFormat::MessagePack);
+        assert_eq!(detect("m.cbor.gz", &[0x81]), OutputFormat::Cbor);
+        assert_eq!(detect("m.bin", &[0x92]), OutputFormat::MessagePack);
+        assert_eq!(detect("m.bin", b"[{"), OutputFormat::Json);
+    }
 }
@@ -0,0 +1,118 @@
+//! Perft: counting move sequences to validate the move generator.
+//!
+//! `perft(n)` is the number of legal move sequences of exactly `n` plies
+//! from a position, where no sequence continues past a won or full board.
+//! Known values from the empty board are a regression oracle for
+//! `Board::play`, `Board::is_winning_move` and any future board
+//! representation.
+
+use crate::{parse_start, AppConfig, Board, Player};
+use rayon::prelude::*;
+use std::time::Instant;
+
+/// Count the leaves `depth` plies below `board`, with `player` to move.
+pub fn perft(board: &mut Board, player: Player, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let mut nodes = 0;
+    for col in 0..7 {
+        let Some((row, col)) = board.play(col, player) else {
+            continue;
+        };
+        if !board.is_winning_move(row, col, player) {
+            nodes += perft(board, player.opponent(), depth - 1);
+        } else if depth == 1 {
+            // The winning move itself is a leaf; nothing follows it
+            nodes += 1;
+        }
+        board.undo(col);
+    }
+    nodes
+}
+
+/// `perft` split per root move, counting each root move's subtree in
+/// parallel. Returns (column, leaves) for every playable column. `depth`
+/// must be at least 1, since the split is by the first move.
+pub fn perft_divide(board: &Board, depth: usize) -> Vec<(usize, u64)> {
+    assert!(depth > 0, "perft_divide needs a depth of at least 1");
+    let player = board.side_to_move();
+    board
+        .valid_moves()
+        .into_par_iter()
+        .map(|col| {
+            let mut board = board.clone();
+            let (row, col) = board.play(col, player).unwrap();
+            let nodes = if board.is_winning_move(row, col, player) {
+                (depth == 1) as u64
+            } else {
+                perft(&mut board, player.opponent(), depth - 1)
+            };
+            (col, nodes)
+        })
+        .collect()
+}
+
+pub fn do_perft(config: AppConfig) {
+    if config.depth == 0 {
+        eprintln!("Error: --depth must be at least 1 in perft mode");
+        std::process::exit(1);
+    }
+    let board = match &config.position {
+        Some(position) => parse_start(position).1,
+        None => Board::new(),
+    };
+
+    let started = Instant::now();
+    let divide = perft_divide(&board, config.depth);
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let total: u64 = divide.iter().map(|&(_, nodes)| nodes).sum();
+    for (col, nodes) in &divide {
+        println!("{}: {}", col + 1, nodes);
+    }
+    println!();
+    println!("perft({}) = {}", config.depth, total);
+    println!(
+        "{:.3}s, {:.0} leaves/s",
+        elapsed,
+        total as f64 / elapsed.max(1e-9)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perft_from_empty_board() {
+        // No game can end before ply 7, so the counts are powers of 7 until
+        // ply 7, where the 7 sequences overfilling a single column drop out.
+        let expected = [1, 7, 49, 343, 2401, 16807, 117649, 823536];
+        for (depth, &nodes) in expected.iter().enumerate() {
+            assert_eq!(perft(&mut Board::new(), Player::Yellow, depth), nodes);
+        }
+        let total: u64 = perft_divide(&Board::new(), 7)
+            .iter()
+            .map(|&(_, nodes)| nodes)
+            .sum();
+        assert_eq!(total, 823536);
+    }
+
+    #[test]
+    fn test_perft_stops_at_terminal_positions() {
+        // Yellow threatens to win in column 1 or 5; winning lines end there.
+        let board: Board = "27374".parse().unwrap();
+        let divide = perft_divide(&board, 2);
+        assert_eq!(divide.len(), 7);
+        assert!(divide.iter().all(|&(_, nodes)| nodes == 7));
+
+        let board: Board = "273747".parse().unwrap();
+        let divide = perft_divide(&board, 1);
+        assert_eq!(divide.iter().map(|&(_, n)| n).sum::<u64>(), 7);
+        let divide = perft_divide(&board, 2);
+        // After Yellow's wins in column 1 or 5 the game is over.
+        assert_eq!(divide[0], (0, 0));
+        assert_eq!(divide[4], (4, 0));
+    }
+}
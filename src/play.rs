@@ -0,0 +1,333 @@
+use crate::agent::{parse_agent, Agent};
+use crate::compress::Compression;
+use crate::render::Renderer;
+use crate::{
+    load_matches, parse_start, print_board, save_matches, AppConfig, Board, Match, MoveRecord,
+    Player,
+};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+/// A single line of input from the human player.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Drop(usize),
+    Undo,
+    Hint,
+    Quit,
+}
+
+/// Parse a prompt answer. Columns are entered 1-indexed, as printed in hints.
+fn parse_command(line: &str) -> Result<Command, String> {
+    match line.trim().to_lowercase().as_str() {
+        "u" | "undo" => Ok(Command::Undo),
+        "h" | "hint" => Ok(Command::Hint),
+        "q" | "quit" | "exit" => Ok(Command::Quit),
+        other => match other.parse::<usize>() {
+            Ok(col) if (1..=7).contains(&col) => Ok(Command::Drop(col - 1)),
+            _ => Err(format!(
+                "Invalid input: {} (expected a column 1-7, u, h or q)",
+                other
+            )),
+        },
+    }
+}
+
+/// Format 0-indexed positions as the 1-indexed column list shown to the player.
+fn columns_label(positions: &[(usize, usize)]) -> String {
+    positions
+        .iter()
+        .map(|&(_, col)| (col + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_hint(board: &Board, human: Player) {
+    let (has_win, wins) = board.immediate_wins(human);
+    let (has_threat, threats) = board.immediate_wins(human.opponent());
+
+    if has_win {
+        println!(
+            "Hint: you can win right now in column {}",
+            columns_label(&wins)
+        );
+    }
+    if has_threat {
+        println!(
+            "Hint: your opponent threatens to win in column {}",
+            columns_label(&threats)
+        );
+    }
+    if !has_win && !has_threat {
+        println!("Hint: no immediate wins or threats on the board");
+    }
+}
+
+/// Take back the human's most recent move together with any agent reply that
+/// followed it. Returns false if the human has not moved yet.
+fn undo_turn(board: &mut Board, moves: &mut Vec<MoveRecord>, human: Player) -> bool {
+    let Some(index) = moves.iter().rposition(|m| m.player == human) else {
+        return false;
+    };
+    for m in moves.drain(index..).rev() {
+        board.undo(m.usr_move);
+    }
+    true
+}
+
+/// Play one game from `start` between the human (reading commands from
+/// `input`) and `agent`. Returns the recorded moves, or None if the human
+/// quit early.
+fn play_game<R: BufRead>(
+    agent: &dyn Agent,
+    human: Player,
+    start: &Board,
+    renderer: &Renderer,
+    input: &mut R,
+) -> Option<Vec<MoveRecord>> {
+    let mut rng = rand::rng();
+    let mut board = start.clone();
+    let mut moves: Vec<MoveRecord> = Vec::new();
+    let mut current_player = start.side_to_move();
+
+    loop {
+        print_board(&board, renderer);
+
+        if board.valid_moves().is_empty() {
+            println!("The board is full: it's a draw!");
+            return Some(moves);
+        }
+
+        let (has_immediate_win, immediate_win_positions) = board.immediate_wins(current_player);
+
+        let (col, policy) = if current_player == human {
+            print!("Your move ({:?}) [1-7, u=undo, h=hint, q=quit]: ", human);
+            io::stdout().flush().unwrap();
+
+            let mut line = String::new();
+            if input.read_line(&mut line).unwrap_or(0) == 0 {
+                return None;
+            }
+            match parse_command(&line) {
+                Ok(Command::Drop(col)) if board.can_play(col) => (col, "human".to_string()),
+                Ok(Command::Drop(col)) => {
+                    println!("Column {} is full", col + 1);
+                    continue;
+                }
+                Ok(Command::Undo) => {
+                    if !undo_turn(&mut board, &mut moves, human) {
+                        println!("Nothing to undo");
+                    }
+                    current_player = human;
+                    continue;
+                }
+                Ok(Command::Hint) => {
+                    print_hint(&board, human);
+                    continue;
+                }
+                Ok(Command::Quit) => return None,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            }
+        } else {
+            let (col, policy) = agent.choose_with_policy(&board, current_player, &mut rng);
+            println!(
+                "{} ({:?}) plays column {}",
+                agent.name(),
+                current_player,
+                col + 1
+            );
+            (col, policy)
+        };
+
+        let (row, col) = board.play(col, current_player).unwrap();
+        moves.push(MoveRecord {
+            usr_move: col,
+            has_immediate_win,
+            immediate_win_positions,
+            player: current_player,
+            policy: Some(policy),
+            threats: None,
+            annotation: None,
+        });
+
+        if board.is_winning_move(row, col, current_player) {
+            print_board(&board, renderer);
+            if current_player == human {
+                println!("You win!");
+            } else {
+                println!("{} wins!", agent.name());
+            }
+            return Some(moves);
+        }
+
+        current_player = current_player.opponent();
+    }
+}
+
+/// Append a finished game to the dataset at `path`, creating it if needed.
+/// The new match gets the next free id.
+fn append_match(
+    config: &AppConfig,
+    path: &Path,
+    start: Option<String>,
+    moves: Vec<MoveRecord>,
+) -> Result<usize, String> {
+    let mut matches = if path.exists() {
+        load_matches(path)?
+    } else {
+        Vec::new()
+    };
+    let id = matches.iter().map(|m| m.id).max().unwrap_or(0) + 1;
+    matches.push(Match {
+        start,
+        ..Match::new(id, moves)
+    });
+    save_matches(
+        &matches,
+        config.output_format,
+        path,
+        config.store_immediate_wins,
+        Compression::for_output(config.compress, config.compression_level, path),
+        config.checksum,
+    )?;
+    Ok(id)
+}
+
+pub fn do_play(config: AppConfig) {
+    let agent = parse_agent(&config.agent).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let human = if config.human_first {
+        Player::Yellow
+    } else {
+        Player::Red
+    };
+
+    // Each game is added to the games already saved, so they must be read back
+    if config.output_file.is_some() && !config.output_format.is_readable() {
+        eprintln!("Error: Saving games needs an output format that can be read back: json, jsonlite, msgpack, cbor or sqlite");
+        std::process::exit(1);
+    }
+    if let Some(path) = &config.output_file {
+        let compression = Compression::for_output(config.compress, config.compression_level, path);
+        if let Err(e) = config.output_format.check_compression(compression) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let (start, start_board) = match &config.position {
+        Some(position) => {
+            let (notation, board) = parse_start(position);
+            (Some(notation), board)
+        }
+        None => (None, Board::new()),
+    };
+
+    println!("=== Connect-4: you ({:?}) vs {} ===", human, agent.name());
+    let stdin = io::stdin();
+    let renderer = Renderer::new(config.render, config.coordinates);
+    let Some(moves) = play_game(
+        agent.as_ref(),
+        human,
+        &start_board,
+        &renderer,
+        &mut stdin.lock(),
+    ) else {
+        println!("Game abandoned, nothing saved.");
+        return;
+    };
+
+    if let Some(path) = &config.output_file {
+        match append_match(&config, path, start, moves) {
+            Ok(id) => println!("Saved game as match {} in {}", id, path.display()),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::RenderStyle;
+    use rand::RngCore;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("4\n"), Ok(Command::Drop(3)));
+        assert_eq!(parse_command(" U "), Ok(Command::Undo));
+        assert_eq!(parse_command("hint"), Ok(Command::Hint));
+        assert_eq!(parse_command("q"), Ok(Command::Quit));
+        assert!(parse_command("0").is_err());
+        assert!(parse_command("8").is_err());
+        assert!(parse_command("left").is_err());
+    }
+
+    #[test]
+    fn test_undo_turn_removes_agent_reply() {
+        let mut board = Board::new();
+        let mut moves = Vec::new();
+        for (col, player) in [
+            (3, Player::Yellow),
+            (2, Player::Red),
+            (3, Player::Yellow),
+            (4, Player::Red),
+        ] {
+            board.play(col, player);
+            moves.push(MoveRecord::new(col, player));
+        }
+
+        assert!(undo_turn(&mut board, &mut moves, Player::Yellow));
+        assert_eq!(moves.len(), 2);
+        assert_eq!(board.grid[4][3], None);
+        assert_eq!(board.grid[5][4], None);
+        assert_eq!(board.grid[5][3], Some(Player::Yellow));
+
+        assert!(!undo_turn(&mut Board::new(), &mut Vec::new(), Player::Red));
+    }
+
+    /// Always plays the rightmost playable column.
+    struct RightmostAgent;
+
+    impl Agent for RightmostAgent {
+        fn name(&self) -> String {
+            "rightmost".to_string()
+        }
+
+        fn choose_move(&self, board: &Board, _player: Player, _rng: &mut dyn RngCore) -> usize {
+            *board.valid_moves().last().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_play_game_from_input() {
+        let play = |input: &str| {
+            play_game(
+                &RightmostAgent,
+                Player::Yellow,
+                &Board::new(),
+                &Renderer::new(RenderStyle::Ascii, false),
+                &mut io::Cursor::new(input),
+            )
+        };
+
+        // Yellow stacks column 1 and wins on its fourth disc
+        let moves = play("1\n1\nh\n1\n1\n").unwrap();
+        let columns: Vec<usize> = moves.iter().map(|m| m.usr_move).collect();
+        assert_eq!(columns, vec![0, 6, 0, 6, 0, 6, 0]);
+        assert_eq!(moves[6].player, Player::Yellow);
+        assert_eq!(moves[6].policy.as_deref(), Some("human"));
+        assert_eq!(moves[1].policy.as_deref(), Some("rightmost"));
+
+        // Quitting, or running out of input, abandons the game
+        assert!(play("1\nq\n1\n").is_none());
+        assert!(play("1\n").is_none());
+    }
+}
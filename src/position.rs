@@ -0,0 +1,316 @@
+//! Text notations for positions:
+//!
+//! * move sequences: the columns played from the empty board, 1-indexed,
+//!   e.g. `4455324`;
+//! * grids: 42 cells read row by row from the top, `.` for empty, `Y` (or
+//!   `X`) for Yellow and `R` (or `O`) for Red, optionally followed by a space
+//!   and the side to move, e.g. `...(35 more)..RYY.. Y`.
+//!
+//! Both parse into a `Board` through `FromStr`, which picks the notation
+//! from the text and rejects positions no game can reach. Start positions
+//! must additionally not be won already, see `normalize_position`.
+
+use crate::compress;
+use crate::{Board, MoveRecord, Player};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Letter used for `player` in grid notation.
+fn player_char(player: Player) -> char {
+    match player {
+        Player::Yellow => 'Y',
+        Player::Red => 'R',
+    }
+}
+
+/// Play a 1-indexed move sequence from the empty board, checking that every
+/// move is legal and that no move follows a win. Errors name the offending
+/// ply (1-based).
+fn play_moves(s: &str) -> Result<Board, String> {
+    let mut board = Board::new();
+    let mut player = Player::Yellow;
+    let mut won = false;
+    for (i, c) in s.chars().enumerate() {
+        let ply = i + 1;
+        if won {
+            return Err(format!("Ply {}: the game was already won", ply));
+        }
+        let col = match c.to_digit(10) {
+            Some(col @ 1..=7) => col as usize - 1,
+            _ => return Err(format!("Ply {}: '{}' is not a column (1-7)", ply, c)),
+        };
+        let Some((row, col)) = board.play(col, player) else {
+            return Err(format!("Ply {}: column {} is full", ply, c));
+        };
+        won = board.is_winning_move(row, col, player);
+        player = player.opponent();
+    }
+    Ok(board)
+}
+
+/// Write the columns of `moves` as a 1-indexed move sequence.
+pub fn format_moves(moves: &[MoveRecord]) -> String {
+    moves
+        .iter()
+        .map(|m| char::from(b'1' + m.usr_move as u8))
+        .collect()
+}
+
+fn parse_grid(s: &str) -> Result<Board, String> {
+    let (cells, side) = match s.split_once(char::is_whitespace) {
+        Some((cells, side)) => (cells, Some(side.trim())),
+        None => (s, None),
+    };
+    if cells.chars().count() != 42 {
+        return Err(format!(
+            "Expected a move sequence or a 42-cell grid, got: {}",
+            s
+        ));
+    }
+    let mut board = Board::new();
+    for (i, c) in cells.chars().enumerate() {
+        board.grid[i / 7][i % 7] = match c.to_ascii_uppercase() {
+            '.' => None,
+            'Y' | 'X' => Some(Player::Yellow),
+            'R' | 'O' => Some(Player::Red),
+            _ => return Err(format!("Invalid cell '{}' in grid {}", c, s)),
+        };
+    }
+
+    let count = |player| {
+        board
+            .grid
+            .iter()
+            .flatten()
+            .filter(|&&cell| cell == Some(player))
+            .count()
+    };
+    let (yellow, red) = (count(Player::Yellow), count(Player::Red));
+    if yellow != red && yellow != red + 1 {
+        return Err(format!(
+            "Grid has {} Yellow and {} Red discs, which no game reaches: {}",
+            yellow, red, s
+        ));
+    }
+    for row in 0..5 {
+        for col in 0..7 {
+            if board.grid[row][col].is_some() && board.grid[row + 1][col].is_none() {
+                return Err(format!("Floating disc in column {} of grid {}", col + 1, s));
+            }
+        }
+    }
+    // Only the side that moved last can have four in a row, and only if its
+    // last disc made the four, since no game continues after a win
+    let to_move = board.side_to_move();
+    if has_four(&board, to_move) {
+        return Err(format!(
+            "{:?} has four in a row but {:?} moved last: {}",
+            to_move,
+            to_move.opponent(),
+            s
+        ));
+    }
+    let last = to_move.opponent();
+    if has_four(&board, last) && !(0..7).any(|col| was_winning_move(&board, col, last)) {
+        return Err(format!(
+            "Play continued after {:?} had four in a row: {}",
+            last, s
+        ));
+    }
+
+    if let Some(side) = side {
+        let expected = player_char(board.side_to_move());
+        if !side.eq_ignore_ascii_case(&expected.to_string()) {
+            return Err(format!(
+                "Side to move '{}' does not match the grid, {} is to move: {}",
+                side, expected, s
+            ));
+        }
+    }
+    Ok(board)
+}
+
+fn has_four(board: &Board, player: Player) -> bool {
+    (0..6).any(|row| {
+        (0..7).any(|col| {
+            board.grid[row][col] == Some(player) && board.winning_line(row, col, player).is_some()
+        })
+    })
+}
+
+/// Whether `player`'s top disc in `col` could have been the game's last
+/// move: taking it back leaves `player` without four in a row.
+fn was_winning_move(board: &Board, col: usize, player: Player) -> bool {
+    let Some(row) = (0..6).find(|&row| board.grid[row][col].is_some()) else {
+        return false;
+    };
+    let mut before = board.clone();
+    before.grid[row][col] = None;
+    board.grid[row][col] == Some(player) && !has_four(&before, player)
+}
+
+impl FromStr for Board {
+    type Err = String;
+
+    /// Parse either notation; a string made only of digits (including the
+    /// empty string) is a move sequence.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.chars().all(|c| c.is_ascii_digit()) {
+            play_moves(s)
+        } else {
+            parse_grid(s)
+        }
+    }
+}
+
+impl Board {
+    /// The side with four in a row, if any.
+    pub fn winner(&self) -> Option<Player> {
+        (0..6).find_map(|row| {
+            (0..7).find_map(|col| {
+                self.grid[row][col].filter(|&player| self.winning_line(row, col, player).is_some())
+            })
+        })
+    }
+
+    /// The position in grid notation, with the side to move.
+    pub fn to_grid_notation(&self) -> String {
+        let cells: String = self
+            .grid
+            .iter()
+            .flatten()
+            .map(|cell| cell.map_or('.', player_char))
+            .collect();
+        format!("{} {}", cells, player_char(self.side_to_move()))
+    }
+}
+
+/// Normalize a position to the notation stored in match files: move
+/// sequences are kept, since they also record the order of play, and grids
+/// are rewritten in canonical form with the side to move.
+pub fn normalize_position(s: &str) -> Result<(String, Board), String> {
+    let s = s.trim();
+    let board: Board = s.parse()?;
+    if let Some(winner) = board.winner() {
+        return Err(format!("{:?} has already won: {}", winner, s));
+    }
+    if s.chars().all(|c| c.is_ascii_digit()) {
+        Ok((s.to_string(), board))
+    } else {
+        Ok((board.to_grid_notation(), board))
+    }
+}
+
+/// Read a start-positions file: one position per line, in either notation.
+/// Blank lines and lines starting with `#` are skipped. Returns each
+/// position in normalized notation alongside its board.
+pub fn load_start_positions(path: &Path) -> Result<Vec<(String, Board)>, String> {
+    let text = compress::read_to_string(path)
+        .map_err(|e| format!("Failed to read start positions {}: {}", path.display(), e))?;
+    let positions = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            normalize_position(line).map_err(|e| format!("{}:{}: {}", path.display(), i + 1, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if positions.is_empty() {
+        return Err(format!("No start positions in {}", path.display()));
+    }
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(usr_move: usize) -> MoveRecord {
+        MoveRecord::new(usr_move, Player::Yellow)
+    }
+
+    #[test]
+    fn test_parse_move_sequence() {
+        let board: Board = "4453".parse().unwrap();
+        assert_eq!(board.grid[5][3], Some(Player::Yellow));
+        assert_eq!(board.grid[4][3], Some(Player::Red));
+        assert_eq!(board.grid[5][4], Some(Player::Yellow));
+        assert_eq!(board.grid[5][2], Some(Player::Red));
+        assert_eq!("".parse::<Board>().unwrap().ply(), 0);
+        assert_eq!(format_moves(&[3, 3, 4, 2].map(record)), "4453");
+
+        assert_eq!(
+            "48".parse::<Board>().unwrap_err(),
+            "Ply 2: '8' is not a column (1-7)".to_string()
+        );
+        assert_eq!(
+            "1111111".parse::<Board>().unwrap_err(),
+            "Ply 7: column 1 is full".to_string()
+        );
+        // Yellow completes a vertical four on the seventh ply: the sequence
+        // is a finished game, but nothing may follow it.
+        assert_eq!(
+            "1212121".parse::<Board>().unwrap().winner(),
+            Some(Player::Yellow)
+        );
+        assert_eq!(
+            "12121213".parse::<Board>().unwrap_err(),
+            "Ply 8: the game was already won".to_string()
+        );
+        assert!(normalize_position("1212121").is_err());
+    }
+
+    #[test]
+    fn test_grid_round_trip() {
+        let board: Board = "4453".parse().unwrap();
+        let grid = board.to_grid_notation();
+        assert_eq!(grid.len(), 44);
+        assert!(grid.ends_with("..RYY.. Y"));
+        assert_eq!(grid.parse::<Board>().unwrap().grid, board.grid);
+
+        // Lowercase X/O cells and a missing side to move are accepted too.
+        let legacy = grid[..42].replace('Y', "x").replace('R', "o");
+        assert_eq!(legacy.parse::<Board>().unwrap().grid, board.grid);
+        assert_eq!(normalize_position(&legacy).unwrap().0, grid);
+        assert!(format!("{} R", &grid[..42]).parse::<Board>().is_err());
+    }
+
+    #[test]
+    fn test_parse_grid_rejects_impossible_positions() {
+        let empty = ".".repeat(42);
+        let with = |cells: &[(usize, char)]| {
+            let mut grid: Vec<char> = empty.chars().collect();
+            for &(i, c) in cells {
+                grid[i] = c;
+            }
+            grid.into_iter().collect::<String>()
+        };
+        // Red cannot have more discs than Yellow.
+        assert!(with(&[(38, 'R')]).parse::<Board>().is_err());
+        // Discs must rest on the bottom row or another disc.
+        assert!(with(&[(31, 'Y')]).parse::<Board>().is_err());
+        assert!(with(&[(38, 'Y')]).parse::<Board>().is_ok());
+        assert!("YYY".parse::<Board>().is_err());
+        // Red cannot have won when Yellow moved last.
+        let red_four = with(&[
+            (35, 'R'),
+            (36, 'R'),
+            (37, 'R'),
+            (38, 'R'),
+            (39, 'Y'),
+            (40, 'Y'),
+            (41, 'Y'),
+            (32, 'Y'),
+            (33, 'Y'),
+        ]);
+        assert!(red_four.parse::<Board>().is_err());
+        // Yellow may have won with its last disc, but not kept playing after.
+        let yellow_four = [(35, 'Y'), (28, 'Y'), (21, 'Y'), (14, 'Y')];
+        let won = [&yellow_four[..], &[(36, 'R'), (37, 'R'), (38, 'R')]].concat();
+        assert!(with(&won).parse::<Board>().is_ok());
+        let continued = [&won[..], &[(7, 'R'), (41, 'Y')]].concat();
+        assert!(with(&continued).parse::<Board>().is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! Progress reporting for long generation runs.
+//!
+//! Rayon workers count finished matches and the positions (plies) they
+//! played in atomic counters, and a reporter thread prints throughput,
+//! elapsed time and ETA at a fixed interval: as a line on stderr, redrawn in
+//! place on a terminal, or as JSON lines on stdout with `--progress-json`.
+
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// How progress is shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressMode {
+    /// A line on stderr.
+    Human,
+    /// A JSON object per line on stdout, and no other output there.
+    Json,
+    /// Nothing but errors.
+    Quiet,
+}
+
+impl ProgressMode {
+    /// Whether the usual messages on stdout should be printed.
+    pub fn announces(self) -> bool {
+        self == ProgressMode::Human
+    }
+
+    fn interval(self) -> Duration {
+        match self {
+            // Redrawn in place on a terminal, otherwise one line per 10s
+            ProgressMode::Human if std::io::stderr().is_terminal() => Duration::from_secs(1),
+            _ => Duration::from_secs(10),
+        }
+    }
+}
+
+/// Counters shared with the workers of a run of `total` matches, of which
+/// `initial` were already generated (by an interrupted run) when it started.
+pub struct Progress {
+    mode: ProgressMode,
+    total: usize,
+    initial: usize,
+    done: AtomicUsize,
+    positions: AtomicU64,
+    start: Instant,
+}
+
+/// The progress of a run at one point in time.
+#[derive(Debug, PartialEq, Serialize)]
+struct Snapshot {
+    done: usize,
+    total: usize,
+    positions: u64,
+    elapsed_secs: f64,
+    matches_per_sec: f64,
+    positions_per_sec: f64,
+    /// None until the rate is known.
+    eta_secs: Option<f64>,
+    finished: bool,
+}
+
+impl Progress {
+    pub fn new(mode: ProgressMode, total: usize, initial: usize) -> Self {
+        Self {
+            mode,
+            total,
+            initial,
+            done: AtomicUsize::new(initial),
+            positions: AtomicU64::new(0),
+            start: Instant::now(),
+        }
+    }
+
+    /// Count a finished match of `plies` moves. Called from any thread.
+    pub fn record(&self, plies: usize) {
+        self.positions.fetch_add(plies as u64, Ordering::Relaxed);
+        self.done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Rates only count the matches generated since this run started.
+    fn snapshot(&self, elapsed: Duration, finished: bool) -> Snapshot {
+        let done = self.done.load(Ordering::Relaxed);
+        let positions = self.positions.load(Ordering::Relaxed);
+        let elapsed_secs = elapsed.as_secs_f64();
+        let rate = |count: f64| {
+            if elapsed_secs > 0.0 {
+                count / elapsed_secs
+            } else {
+                0.0
+            }
+        };
+        let matches_per_sec = rate((done - self.initial) as f64);
+        Snapshot {
+            done,
+            total: self.total,
+            positions,
+            elapsed_secs,
+            matches_per_sec,
+            positions_per_sec: rate(positions as f64),
+            eta_secs: (matches_per_sec > 0.0)
+                .then(|| self.total.saturating_sub(done) as f64 / matches_per_sec),
+            finished,
+        }
+    }
+
+    fn report(&self, finished: bool) {
+        let snapshot = self.snapshot(self.start.elapsed(), finished);
+        match self.mode {
+            ProgressMode::Human => {
+                let mut stderr = std::io::stderr().lock();
+                if stderr.is_terminal() {
+                    let end = if finished { "\n" } else { "" };
+                    let _ = write!(stderr, "\r{}\x1b[K{}", format_snapshot(&snapshot), end);
+                } else {
+                    let _ = writeln!(stderr, "{}", format_snapshot(&snapshot));
+                }
+            }
+            ProgressMode::Json => {
+                if let Ok(line) = serde_json::to_string(&snapshot) {
+                    println!("{}", line);
+                }
+            }
+            ProgressMode::Quiet => {}
+        }
+    }
+
+    /// Run `f` while reporting progress every interval from another thread,
+    /// and once more when it returns.
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        if self.mode == ProgressMode::Quiet {
+            return f();
+        }
+        let finished = AtomicBool::new(false);
+        let result = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let interval = self.mode.interval();
+                let mut next = interval;
+                while !finished.load(Ordering::Relaxed) {
+                    std::thread::sleep(Duration::from_millis(100));
+                    if self.start.elapsed() >= next {
+                        self.report(false);
+                        next += interval;
+                    }
+                }
+            });
+            let result = f();
+            finished.store(true, Ordering::Relaxed);
+            result
+        });
+        self.report(true);
+        result
+    }
+}
+
+/// `h:mm:ss`, or `m:ss` under an hour.
+fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
+}
+
+fn format_snapshot(snapshot: &Snapshot) -> String {
+    let percent = if snapshot.total > 0 {
+        100.0 * snapshot.done as f64 / snapshot.total as f64
+    } else {
+        100.0
+    };
+    let eta = snapshot
+        .eta_secs
+        .map_or("unknown".to_string(), format_duration);
+    format!(
+        "{}/{} matches ({:.1}%), {:.0} matches/s, {:.0} positions/s, elapsed {}, ETA {}",
+        snapshot.done,
+        snapshot.total,
+        percent,
+        snapshot.matches_per_sec,
+        snapshot.positions_per_sec,
+        format_duration(snapshot.elapsed_secs),
+        eta
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        // Resumed with 100 of 1000 matches done, then 200 more in 4 seconds
+        let progress = Progress::new(ProgressMode::Quiet, 1000, 100);
+        for _ in 0..200 {
+            progress.record(20);
+        }
+        let snapshot = progress.snapshot(Duration::from_secs(4), false);
+        assert_eq!(snapshot.done, 300);
+        assert_eq!(snapshot.positions, 4000);
+        assert_eq!(snapshot.matches_per_sec, 50.0);
+        assert_eq!(snapshot.positions_per_sec, 1000.0);
+        assert_eq!(snapshot.eta_secs, Some(14.0));
+        assert_eq!(
+            format_snapshot(&snapshot),
+            "300/1000 matches (30.0%), 50 matches/s, 1000 positions/s, elapsed 0:04, ETA 0:14"
+        );
+        assert!(serde_json::to_string(&snapshot)
+            .unwrap()
+            .starts_with("{\"done\":300,\"total\":1000,\"positions\":4000,"));
+
+        let snapshot = Progress::new(ProgressMode::Quiet, 10, 0).snapshot(Duration::ZERO, false);
+        assert_eq!(snapshot.eta_secs, None);
+        assert_eq!(format_duration(3725.0), "1:02:05");
+    }
+}
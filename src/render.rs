@@ -0,0 +1,211 @@
+use crate::{Board, MoveRecord, Player};
+use crossterm::style::Stylize;
+use std::str::FromStr;
+
+/// How discs are drawn in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderStyle {
+    /// `X` for Yellow, `O` for Red, `.` for empty. Safe for logs and CI output.
+    Ascii,
+    /// Colored `●` discs using ANSI escape codes.
+    Ansi,
+    /// The original 🟡/🔴/⚪ emoji.
+    Emoji,
+}
+
+impl FromStr for RenderStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ascii" => Ok(RenderStyle::Ascii),
+            "ansi" => Ok(RenderStyle::Ansi),
+            "emoji" => Ok(RenderStyle::Emoji),
+            _ => Err(format!("Unknown render style: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for RenderStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderStyle::Ascii => write!(f, "ascii"),
+            RenderStyle::Ansi => write!(f, "ansi"),
+            RenderStyle::Emoji => write!(f, "emoji"),
+        }
+    }
+}
+
+/// Turns boards into text. Columns are labelled 1-7 left to right and rows
+/// 1-6 bottom to top when coordinates are enabled.
+#[derive(Debug, Clone, Copy)]
+pub struct Renderer {
+    style: RenderStyle,
+    coordinates: bool,
+}
+
+impl Renderer {
+    pub fn new(style: RenderStyle, coordinates: bool) -> Self {
+        Self { style, coordinates }
+    }
+
+    /// Terminal columns taken by one cell glyph.
+    fn cell_width(&self) -> usize {
+        match self.style {
+            RenderStyle::Emoji => 2,
+            RenderStyle::Ascii | RenderStyle::Ansi => 1,
+        }
+    }
+
+    pub fn cell(&self, cell: Option<Player>) -> String {
+        match (self.style, cell) {
+            (RenderStyle::Ascii, Some(Player::Yellow)) => "X".to_string(),
+            (RenderStyle::Ascii, Some(Player::Red)) => "O".to_string(),
+            (RenderStyle::Ascii, None) => ".".to_string(),
+            (RenderStyle::Ansi, Some(Player::Yellow)) => "●".yellow().to_string(),
+            (RenderStyle::Ansi, Some(Player::Red)) => "●".red().to_string(),
+            (RenderStyle::Ansi, None) => "·".dark_grey().to_string(),
+            (RenderStyle::Emoji, Some(Player::Yellow)) => "🟡".to_string(),
+            (RenderStyle::Emoji, Some(Player::Red)) => "🔴".to_string(),
+            (RenderStyle::Emoji, None) => "⚪".to_string(), // empty
+        }
+    }
+
+    /// Prefix printed before the row with index `row` (0 is the top row).
+    pub fn row_label(&self, row: usize) -> String {
+        if self.coordinates {
+            format!("{} ", 6 - row)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Column numbers aligned with the cells, or None without coordinates.
+    pub fn header(&self) -> Option<String> {
+        if !self.coordinates {
+            return None;
+        }
+        let width = self.cell_width();
+        let mut header = " ".repeat(self.row_label(0).len());
+        for col in 1..=7 {
+            header.push_str(&format!(" {:<width$}", col, width = width));
+        }
+        Some(header.trim_end().to_string())
+    }
+
+    /// Render the whole board, one line per row followed by a separator line.
+    pub fn render(&self, board: &Board) -> String {
+        let mut out = String::new();
+        if let Some(header) = self.header() {
+            out.push_str(&header);
+            out.push('\n');
+        }
+        for row in 0..6 {
+            out.push_str(&self.row_label(row));
+            out.push('|');
+            for col in 0..7 {
+                out.push_str(&self.cell(board.grid[row][col]));
+                out.push('|');
+            }
+            out.push('\n');
+        }
+        let separator = match self.style {
+            RenderStyle::Emoji => 29,
+            RenderStyle::Ascii | RenderStyle::Ansi => 15,
+        };
+        out.push_str(&" ".repeat(self.row_label(0).len()));
+        out.push_str(&"-".repeat(separator));
+        out.push('\n');
+        out
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new(RenderStyle::Emoji, false)
+    }
+}
+
+/// Cells worth highlighting after `ply` moves of a match have been played:
+/// the last disc dropped, the immediate wins its player had available, and
+/// the winning line if that disc completed one.
+#[derive(Default)]
+pub struct Highlights {
+    pub last_drop: Option<(usize, usize)>,
+    pub immediate_wins: Vec<(usize, usize)>,
+    pub winning_line: Vec<(usize, usize)>,
+}
+
+pub fn highlights(board: &Board, moves: &[MoveRecord], ply: usize) -> Highlights {
+    let Some(last) = ply.checked_sub(1).map(|i| &moves[i]) else {
+        return Highlights::default();
+    };
+    // The last disc dropped is the topmost one in its column.
+    let col = last.usr_move;
+    let row = (0..6).find(|&row| board.grid[row][col].is_some());
+    Highlights {
+        last_drop: row.map(|row| (row, col)),
+        immediate_wins: last.immediate_win_positions.clone(),
+        winning_line: row
+            .and_then(|row| board.winning_line(row, col, last.player))
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_style_from_str() {
+        assert_eq!("ASCII".parse::<RenderStyle>(), Ok(RenderStyle::Ascii));
+        assert_eq!("ansi".parse::<RenderStyle>(), Ok(RenderStyle::Ansi));
+        assert_eq!("emoji".parse::<RenderStyle>(), Ok(RenderStyle::Emoji));
+        assert!("svg".parse::<RenderStyle>().is_err());
+    }
+
+    #[test]
+    fn test_render_ascii_with_coordinates() {
+        let mut board = Board::new();
+        board.play(0, Player::Yellow);
+        board.play(6, Player::Red);
+
+        let text = Renderer::new(RenderStyle::Ascii, true).render(&board);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "   1 2 3 4 5 6 7");
+        assert_eq!(lines[1], "6 |.|.|.|.|.|.|.|");
+        assert_eq!(lines[6], "1 |X|.|.|.|.|.|O|");
+        assert_eq!(lines[7], "  ---------------");
+    }
+
+    #[test]
+    fn test_render_emoji_matches_legacy_output() {
+        let text = Renderer::default().render(&Board::new());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "|⚪|⚪|⚪|⚪|⚪|⚪|⚪|");
+        assert_eq!(lines[6], "-----------------------------");
+    }
+
+    #[test]
+    fn test_highlights_winning_line() {
+        let mut moves = Vec::new();
+        for col in 0..3 {
+            moves.push(MoveRecord::new(col, Player::Yellow));
+            moves.push(MoveRecord::new(col, Player::Red));
+        }
+        let mut last = MoveRecord::new(3, Player::Yellow);
+        last.has_immediate_win = true;
+        last.immediate_win_positions = vec![(5, 3)];
+        moves.push(last);
+
+        let board = Board::new().with_moves(&moves);
+        let marks = highlights(&board, &moves, moves.len());
+        assert_eq!(marks.last_drop, Some((5, 3)));
+        assert_eq!(marks.immediate_wins, vec![(5, 3)]);
+        assert_eq!(marks.winning_line, vec![(5, 0), (5, 1), (5, 2), (5, 3)]);
+
+        let marks = highlights(&Board::new(), &moves, 0);
+        assert_eq!(marks.last_drop, None);
+    }
+}
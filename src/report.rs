@@ -0,0 +1,511 @@
+use crate::position::format_moves;
+use crate::{load_matches, AppConfig, Match, Player};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+/// Aggregate statistics shown at the top of a report.
+#[derive(Debug)]
+struct DatasetStats {
+    matches: usize,
+    yellow_wins: usize,
+    red_wins: usize,
+    /// Matches that filled the board without a winner.
+    draws: usize,
+    /// Matches that stopped before anyone won or the board was full.
+    unfinished: usize,
+    /// Number of matches of each length, indexed by number of moves (0..=42).
+    lengths: Vec<usize>,
+    /// How often each column was chosen at each ply (0..42).
+    columns_by_ply: Vec<[usize; 7]>,
+    /// Column choices over the whole dataset, for Yellow and Red.
+    columns_by_player: [[usize; 7]; 2],
+}
+
+fn compute_stats(matches: &[Match]) -> DatasetStats {
+    let mut stats = DatasetStats {
+        matches: matches.len(),
+        yellow_wins: 0,
+        red_wins: 0,
+        draws: 0,
+        unfinished: 0,
+        lengths: vec![0; 43],
+        columns_by_ply: vec![[0; 7]; 42],
+        columns_by_player: [[0; 7]; 2],
+    };
+    for m in matches {
+        // Plies count from the empty board even for matches with a start position
+        let offset = m.start_board().ply();
+        match m.winner() {
+            Some(Player::Yellow) => stats.yellow_wins += 1,
+            Some(Player::Red) => stats.red_wins += 1,
+            None if offset + m.moves.len() == 42 => stats.draws += 1,
+            None => stats.unfinished += 1,
+        }
+        stats.lengths[m.moves.len().min(42)] += 1;
+        for (ply, record) in m.moves.iter().enumerate() {
+            stats.columns_by_ply[offset + ply][record.usr_move] += 1;
+            let side = match record.player {
+                Player::Yellow => 0,
+                Player::Red => 1,
+            };
+            stats.columns_by_player[side][record.usr_move] += 1;
+        }
+    }
+    stats
+}
+
+/// The compact form of a match embedded for the in-page replayer: the moves
+/// in move-sequence notation, who moved first and, for matches with a start
+/// position, its grid notation.
+#[derive(Serialize)]
+struct ReplayData {
+    id: usize,
+    moves: String,
+    first: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+}
+
+fn replay_data(matches: &[Match]) -> Vec<ReplayData> {
+    matches
+        .iter()
+        .map(|m| ReplayData {
+            id: m.id,
+            moves: format_moves(&m.moves),
+            first: match m.moves.first().map(|r| r.player) {
+                Some(Player::Red) => 1,
+                _ => 0,
+            },
+            start: m.start.as_ref().map(|_| m.start_board().to_grid_notation()),
+        })
+        .collect()
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        100.0 * count as f64 / total as f64
+    }
+}
+
+/// One heatmap cell, shaded relative to the largest value in its table.
+fn heat_cell(count: usize, max: usize) -> String {
+    let alpha = if max == 0 {
+        0.0
+    } else {
+        count as f64 / max as f64
+    };
+    format!(
+        "<td style=\"background: rgba(30, 80, 180, {:.3}); color: {}\">{}</td>",
+        alpha,
+        if alpha > 0.5 { "#fff" } else { "#222" },
+        count
+    )
+}
+
+fn outcomes_html(stats: &DatasetStats) -> String {
+    let mut html = String::from(
+        "<table class=\"outcomes\"><tr><th>Outcome</th><th>Matches</th><th>Share</th></tr>",
+    );
+    for (label, count) in [
+        ("Yellow wins", stats.yellow_wins),
+        ("Red wins", stats.red_wins),
+        ("Draws", stats.draws),
+        ("Unfinished", stats.unfinished),
+    ] {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{:.1}%</td></tr>",
+            label,
+            count,
+            percent(count, stats.matches)
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn lengths_html(stats: &DatasetStats) -> String {
+    let max = stats.lengths.iter().copied().max().unwrap_or(0).max(1);
+    let mut html = String::from("<div class=\"histogram\">");
+    for (length, &count) in stats.lengths.iter().enumerate() {
+        let _ = write!(
+            html,
+            "<div class=\"bar\" title=\"{} moves: {} matches\"><div style=\"height: {:.1}%\"></div><span>{}</span></div>",
+            length,
+            count,
+            100.0 * count as f64 / max as f64,
+            length
+        );
+    }
+    html.push_str("</div>");
+    html
+}
+
+fn heatmaps_html(stats: &DatasetStats) -> String {
+    let header: String = (1..=7).map(|col| format!("<th>{}</th>", col)).collect();
+
+    let max = stats
+        .columns_by_player
+        .iter()
+        .flatten()
+        .copied()
+        .max()
+        .unwrap_or(0);
+    let mut html = format!(
+        "<h3>Column choices by player</h3><table class=\"heatmap\"><tr><th></th>{}</tr>",
+        header
+    );
+    for (label, counts) in ["Yellow", "Red"].iter().zip(&stats.columns_by_player) {
+        let _ = write!(html, "<tr><th>{}</th>", label);
+        for &count in counts {
+            html.push_str(&heat_cell(count, max));
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+
+    let max = stats
+        .columns_by_ply
+        .iter()
+        .flatten()
+        .copied()
+        .max()
+        .unwrap_or(0);
+    let _ = write!(
+        html,
+        "<h3>Column choices by ply</h3><table class=\"heatmap\"><tr><th>Ply</th>{}</tr>",
+        header
+    );
+    for (ply, counts) in stats.columns_by_ply.iter().enumerate() {
+        if counts.iter().all(|&count| count == 0) {
+            continue;
+        }
+        let _ = write!(html, "<tr><th>{}</th>", ply + 1);
+        for &count in counts {
+            html.push_str(&heat_cell(count, max));
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+    html
+}
+
+/// Replace each `{{NAME}}` placeholder of `template` with its value in a
+/// single pass, so values containing `{{` are never substituted again.
+fn fill_template(template: &str, values: &[(&str, String)]) -> String {
+    let mut page = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        page.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let filled = values.iter().find_map(|(name, value)| {
+            let placeholder = format!("{{{{{}}}}}", name);
+            Some((value, rest.strip_prefix(placeholder.as_str())?))
+        });
+        match filled {
+            Some((value, after)) => {
+                page.push_str(value);
+                rest = after;
+            }
+            None => {
+                page.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    page.push_str(rest);
+    page
+}
+
+/// Build the whole self-contained page.
+fn report_html(title: &str, matches: &[Match]) -> String {
+    let stats = compute_stats(matches);
+    let data =
+        serde_json::to_string(&replay_data(matches)).expect("Failed to serialize replay data");
+    let summary = format!(
+        "{} matches, average length {:.1} moves",
+        stats.matches,
+        matches.iter().map(|m| m.moves.len()).sum::<usize>() as f64 / stats.matches.max(1) as f64
+    );
+    fill_template(
+        TEMPLATE,
+        &[
+            ("TITLE", html_escape(title)),
+            ("SUMMARY", summary),
+            ("OUTCOMES", outcomes_html(&stats)),
+            ("LENGTHS", lengths_html(&stats)),
+            ("HEATMAPS", heatmaps_html(&stats)),
+            ("DATA", data),
+        ],
+    )
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn do_report(config: AppConfig) {
+    let input_file = config.input_file.as_ref().unwrap();
+    let mut matches = load_matches(input_file).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    let mut title = format!("Connect-4 report: {}", input_file.display());
+    if let Some(id) = config.id {
+        matches.retain(|m| m.id == id);
+        if matches.is_empty() {
+            eprintln!("Error: Match ID {} not found", id);
+            std::process::exit(1);
+        }
+        title = format!("Connect-4 report: match {} of {}", id, input_file.display());
+    }
+
+    let output_path = config
+        .output_file
+        .unwrap_or_else(|| PathBuf::from("report.html"));
+    if let Err(e) = std::fs::write(&output_path, report_html(&title, &matches)) {
+        eprintln!("Error: Failed to write report: {}", e);
+        std::process::exit(1);
+    }
+    println!(
+        "Wrote report for {} matches to {}",
+        matches.len(),
+        output_path.display()
+    );
+}
+
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{{TITLE}}</title>
+<style>
+  body { font-family: system-ui, sans-serif; margin: 2em auto; max-width: 960px; color: #222; }
+  h1 { font-size: 1.5em; }
+  section { margin-bottom: 2.5em; }
+  table { border-collapse: collapse; }
+  th, td { padding: 4px 10px; text-align: right; border: 1px solid #ddd; }
+  .heatmap td { min-width: 3em; }
+  .histogram { display: flex; align-items: flex-end; height: 180px; gap: 2px; }
+  .histogram .bar { flex: 1; height: 100%; display: flex; flex-direction: column; justify-content: flex-end; }
+  .histogram .bar div { background: #1e50b4; }
+  .histogram .bar span { font-size: 9px; text-align: center; color: #666; }
+  #board { display: inline-grid; grid-template-columns: repeat(7, 48px); gap: 6px; padding: 10px;
+           background: #1e50b4; border-radius: 8px; }
+  #board div { width: 48px; height: 48px; border-radius: 50%; background: #ebf0fa; box-sizing: border-box; }
+  #board .yellow { background: #f5c800; }
+  #board .red { background: #d22828; }
+  #board .last { border: 4px solid #141414; }
+  #board .win { border: 5px solid #14aa46; }
+  .controls { margin: 1em 0; }
+  .controls button { min-width: 3em; }
+</style>
+</head>
+<body>
+<h1>{{TITLE}}</h1>
+<p>{{SUMMARY}}</p>
+
+<section>
+<h2>Outcomes</h2>
+{{OUTCOMES}}
+</section>
+
+<section>
+<h2>Match length distribution</h2>
+{{LENGTHS}}
+</section>
+
+<section>
+<h2>Column heatmaps</h2>
+{{HEATMAPS}}
+</section>
+
+<section>
+<h2>Replay</h2>
+<div class="controls">
+  Match id <input id="match-id" type="number" min="0" style="width: 7em">
+  <button id="go">Show</button>
+  <button id="prev-match" title="Previous match">&#8593;</button>
+  <button id="next-match" title="Next match">&#8595;</button>
+</div>
+<div id="board"></div>
+<div class="controls">
+  <button id="first">|&lt;</button>
+  <button id="back">&lt;</button>
+  <button id="forward">&gt;</button>
+  <button id="last">&gt;|</button>
+  <span id="status"></span>
+</div>
+<p><small>Arrow keys step through the match; Home/End jump to the first or last ply.</small></p>
+</section>
+
+<script>
+const MATCHES = {{DATA}};
+const PLAYERS = ["Yellow", "Red"];
+let current = 0;
+let ply = 0;
+
+function boardAt(match, plies) {
+  const grid = Array.from({ length: 6 }, (_, row) =>
+    Array.from({ length: 7 }, (_, col) => {
+      const cell = match.start ? match.start[row * 7 + col] : ".";
+      return cell === "Y" ? 0 : cell === "R" ? 1 : null;
+    }));
+  let last = null;
+  for (let i = 0; i < plies; i++) {
+    const col = Number(match.moves[i]) - 1;
+    const player = (match.first + i) % 2;
+    for (let row = 5; row >= 0; row--) {
+      if (grid[row][col] === null) {
+        grid[row][col] = player;
+        last = [row, col, player];
+        break;
+      }
+    }
+  }
+  return { grid, last };
+}
+
+function winningLine(grid, row, col, player) {
+  for (const [dr, dc] of [[0, 1], [1, 0], [1, 1], [1, -1]]) {
+    const line = [[row, col]];
+    for (const sign of [-1, 1]) {
+      let r = row + sign * dr, c = col + sign * dc;
+      while (r >= 0 && r < 6 && c >= 0 && c < 7 && grid[r][c] === player) {
+        line.push([r, c]);
+        r += sign * dr;
+        c += sign * dc;
+      }
+    }
+    if (line.length >= 4) return line;
+  }
+  return [];
+}
+
+function render() {
+  const match = MATCHES[current];
+  if (!match) return;
+  const { grid, last } = boardAt(match, ply);
+  const win = last ? winningLine(grid, last[0], last[1], last[2]) : [];
+  const board = document.getElementById("board");
+  board.innerHTML = "";
+  for (let row = 0; row < 6; row++) {
+    for (let col = 0; col < 7; col++) {
+      const cell = document.createElement("div");
+      if (grid[row][col] !== null) cell.classList.add(grid[row][col] === 0 ? "yellow" : "red");
+      if (win.some(([r, c]) => r === row && c === col)) cell.classList.add("win");
+      else if (last && last[0] === row && last[1] === col) cell.classList.add("last");
+      board.appendChild(cell);
+    }
+  }
+  let status = `Match ${match.id}: ply ${ply}/${match.moves.length}`;
+  if (last) status += ` - ${PLAYERS[last[2]]} played column ${last[1] + 1}`;
+  if (win.length) status += ` - ${PLAYERS[last[2]]} wins!`;
+  document.getElementById("status").textContent = status;
+  document.getElementById("match-id").value = match.id;
+}
+
+function step(delta) {
+  ply = Math.max(0, Math.min(MATCHES[current].moves.length, ply + delta));
+  render();
+}
+
+function showMatch(index) {
+  if (index < 0 || index >= MATCHES.length) return;
+  current = index;
+  ply = 0;
+  render();
+}
+
+document.getElementById("first").onclick = () => step(-Infinity);
+document.getElementById("back").onclick = () => step(-1);
+document.getElementById("forward").onclick = () => step(1);
+document.getElementById("last").onclick = () => step(Infinity);
+document.getElementById("prev-match").onclick = () => showMatch(current - 1);
+document.getElementById("next-match").onclick = () => showMatch(current + 1);
+document.getElementById("go").onclick = () => {
+  const id = Number(document.getElementById("match-id").value);
+  const index = MATCHES.findIndex((m) => m.id === id);
+  if (index >= 0) showMatch(index);
+  else document.getElementById("status").textContent = `Match ${id} not found`;
+};
+document.addEventListener("keydown", (event) => {
+  if (event.target.tagName === "INPUT") return;
+  const actions = { ArrowLeft: -1, ArrowRight: 1, Home: -Infinity, End: Infinity };
+  if (event.key in actions) {
+    step(actions[event.key]);
+    event.preventDefault();
+  } else if (event.key === "ArrowUp") {
+    showMatch(current - 1);
+    event.preventDefault();
+  } else if (event.key === "ArrowDown") {
+    showMatch(current + 1);
+    event.preventDefault();
+  }
+});
+render();
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MoveRecord;
+
+    fn make_match(id: usize, cols: &[usize]) -> Match {
+        Match::new(id, MoveRecord::alternating(cols))
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        let matches = vec![
+            make_match(1, &[0, 1, 0, 1, 0, 1, 0]),
+            make_match(2, &[6, 0, 1, 0, 1, 0, 2, 0]),
+            make_match(3, &[3, 3]),
+        ];
+        let stats = compute_stats(&matches);
+        assert_eq!(stats.matches, 3);
+        assert_eq!(stats.yellow_wins, 1);
+        assert_eq!(stats.red_wins, 1);
+        assert_eq!(stats.draws, 0);
+        assert_eq!(stats.unfinished, 1);
+        assert_eq!(stats.lengths[7], 1);
+        assert_eq!(stats.lengths[8], 1);
+        assert_eq!(stats.lengths[2], 1);
+        assert_eq!(stats.columns_by_ply[0], [1, 0, 0, 1, 0, 0, 1]);
+        assert_eq!(stats.columns_by_player[0][0], 4);
+        assert_eq!(stats.columns_by_player[1][0], 4);
+
+        // A full board without a winner is a draw, also from a start position
+        let line = "257771314744647214154617633623313656555222";
+        let cols: Vec<usize> = line.bytes().map(|b| (b - b'1') as usize).collect();
+        let mut from_start = make_match(5, &cols[40..]);
+        from_start.start = Some(line[..40].to_string());
+        let stats = compute_stats(&[make_match(4, &cols), from_start]);
+        assert_eq!(stats.draws, 2);
+        assert_eq!(stats.unfinished, 0);
+    }
+
+    #[test]
+    fn test_report_html_is_self_contained() {
+        let html = report_html("<test>", &[make_match(7, &[3, 2, 3])]);
+        assert!(html.contains("<title>&lt;test&gt;</title>"));
+        assert!(html.contains(r#"{"id":7,"moves":"434","first":0}"#));
+        assert!(!html.contains("{{"));
+        assert!(!html.contains("http://") && !html.contains("https://"));
+
+        // Placeholders in the title are left as they are
+        let html = report_html("{{DATA}} {{SUMMARY", &[]);
+        assert!(html.contains("<title>{{DATA}} {{SUMMARY</title>"));
+        assert_eq!(html.matches("const MATCHES = [];").count(), 1);
+    }
+}
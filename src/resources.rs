@@ -0,0 +1,173 @@
+//! Resource controls for generation on shared machines: the size of the
+//! thread pool (`--threads`), a soft memory limit (`--max-memory`) and a
+//! time limit (`--time-limit`).
+//!
+//! Generation has no streaming writer: each output file is held in memory
+//! until it is saved. The memory limit therefore sizes both the batches
+//! generated between checkpoints and the output files, splitting a run into
+//! shards when its output would not fit. Memory use is estimated from the
+//! size of a match, not measured.
+
+use crate::{Match, MoveRecord};
+use std::time::{Duration, Instant};
+
+/// Matches generated between two checkpoints when memory is not limited.
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// A game lasts at most 42 plies.
+const MAX_PLIES: usize = 42;
+
+/// Share of `--max-memory` that a batch of matches in flight may use; the
+/// rest is left for the matches held until their output file is saved.
+const BATCH_SHARE: u64 = 4;
+
+/// How a generation run is paced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Matches generated in parallel between two checkpoints.
+    pub batch_size: usize,
+    /// When to stop generating and save what is done.
+    pub deadline: Option<Instant>,
+}
+
+impl Limits {
+    pub fn new(
+        max_memory: Option<u64>,
+        time_limit: Option<Duration>,
+        start: Instant,
+    ) -> Result<Self, String> {
+        let deadline = match time_limit {
+            Some(limit) => Some(
+                start
+                    .checked_add(limit)
+                    .ok_or_else(|| format!("--time-limit of {}s is too long", limit.as_secs()))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            batch_size: batch_size(max_memory),
+            deadline,
+        })
+    }
+
+    pub fn expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// An upper estimate of the memory a generated match takes, ignoring the
+/// few small heap allocations of its records.
+pub fn match_bytes() -> u64 {
+    (std::mem::size_of::<Match>() + MAX_PLIES * std::mem::size_of::<MoveRecord>()) as u64
+}
+
+/// Matches per batch: the default, or fewer if a batch would take more than
+/// its share of `max_memory`.
+fn batch_size(max_memory: Option<u64>) -> usize {
+    max_memory.map_or(DEFAULT_BATCH_SIZE, |max_memory| {
+        let fits = max_memory / BATCH_SHARE / match_bytes();
+        (fits as usize).clamp(1, DEFAULT_BATCH_SIZE)
+    })
+}
+
+/// Whether the `held` matches of an output file, kept in memory until it is
+/// saved, are likely to fit in `max_memory`.
+pub fn fits(max_memory: u64, held: usize) -> bool {
+    held as u64 * match_bytes() <= max_memory
+}
+
+/// Matches per shard that fit in `max_memory` next to a batch in flight.
+pub fn fitting_shard_size(max_memory: u64) -> usize {
+    (max_memory * (BATCH_SHARE - 1) / BATCH_SHARE / match_bytes()).max(1) as usize
+}
+
+/// Parse a size in bytes with an optional K, M, G or T suffix (powers of
+/// 1024, with or without a trailing `B` or `iB`), e.g. `512M` or `2GiB`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let error = || format!("Invalid size: {} (expected e.g. 512M or 2G)", s);
+    let upper = s.trim().to_uppercase();
+    let digits = upper.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let shift = match &upper[digits.len()..] {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        "T" | "TB" | "TIB" => 40,
+        _ => return Err(error()),
+    };
+    let value: u64 = digits.trim().parse().map_err(|_| error())?;
+    value.checked_mul(1 << shift).ok_or_else(error)
+}
+
+/// Parse a duration in seconds with an optional s, m, h or d suffix, e.g.
+/// `90s`, `30m` or `2h`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let error = || format!("Invalid duration: {} (expected e.g. 90s, 30m or 2h)", s);
+    let s = s.trim();
+    let digits = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = match &s[digits.len()..] {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(error()),
+    };
+    let value: f64 = digits.trim().parse().map_err(|_| error())?;
+    Duration::try_from_secs_f64(value * unit as f64).map_err(|_| error())
+}
+
+/// Size rayon's global thread pool, which every parallel command and zstd
+/// compression use. Must be called before anything runs on the pool.
+pub fn configure_threads(threads: usize) -> Result<(), String> {
+    if threads == 0 {
+        return Err("--threads must be at least 1".to_string());
+    }
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .map_err(|e| format!("Failed to start {} threads: {}", threads, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("1kb"), Ok(1024));
+        assert!(parse_size("12X").is_err());
+        assert!(parse_size("M").is_err());
+
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration("2w").is_err());
+        assert!(parse_duration("1e300").is_err());
+        assert!(parse_duration("inf").is_err());
+
+        let start = Instant::now();
+        let limit = parse_duration("1e19").unwrap();
+        assert!(Limits::new(None, Some(limit), start).is_err());
+        let limits = Limits::new(None, Some(Duration::from_secs(60)), start).unwrap();
+        assert_eq!(limits.deadline, Some(start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_memory_sizing() {
+        assert_eq!(batch_size(None), DEFAULT_BATCH_SIZE);
+        assert_eq!(batch_size(Some(1 << 40)), DEFAULT_BATCH_SIZE);
+        assert_eq!(batch_size(Some(40 * match_bytes())), 10);
+        assert_eq!(batch_size(Some(1)), 1);
+
+        let max_memory = 100 * match_bytes();
+        assert!(fits(max_memory, 100));
+        assert!(!fits(max_memory, 101));
+        assert_eq!(fitting_shard_size(max_memory), 75);
+        assert_eq!(fitting_shard_size(1), 1);
+    }
+}
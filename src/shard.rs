@@ -0,0 +1,278 @@
+//! Sharded datasets: generation split across several files listed in a
+//! manifest, and reading a manifest or a glob of files back as one dataset.
+//!
+//! Output `out/matches.json.zst` in 16 shards gives the files
+//! `out/matches-00000-of-00016.json.zst` to `out/matches-00015-of-00016.json.zst`
+//! and the manifest `out/matches.manifest.json`, which records each shard's
+//! match ids and SHA-256 checksum.
+
+use crate::atomic;
+use crate::compress::{self, Codec};
+use crate::integrity::sha256_file;
+use crate::{load_matches, Match};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+/// One shard of a dataset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShardInfo {
+    /// File name of the shard, next to the manifest.
+    pub path: String,
+    pub first_id: usize,
+    pub last_id: usize,
+    pub num_matches: usize,
+    /// Lowercase hex SHA-256 of the shard file as written.
+    pub sha256: String,
+}
+
+/// The list of shards making up a dataset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub num_matches: usize,
+    pub shards: Vec<ShardInfo>,
+}
+
+impl ShardInfo {
+    /// Describe the shard written at `path`, holding the matches `ids`.
+    pub fn new(path: &Path, ids: RangeInclusive<usize>) -> Result<Self, String> {
+        Ok(Self {
+            path: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            first_id: *ids.start(),
+            last_id: *ids.end(),
+            num_matches: ids.count(),
+            sha256: sha256_file(path)?,
+        })
+    }
+}
+
+impl Manifest {
+    pub fn new(format: &str, shards: Vec<ShardInfo>) -> Self {
+        Self {
+            format: format.to_string(),
+            num_matches: shards.iter().map(|shard| shard.num_matches).sum(),
+            shards,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to write manifest: {}", e))?;
+        atomic::write_atomically(path, json).map_err(|e| format!("Failed to write manifest: {}", e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read manifest {}: {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse manifest {}: {}", path.display(), e))
+    }
+}
+
+/// The 0-indexed match ranges of the shards: chunks of `shard_size`
+/// matches, or `num_shards` shards as equal as possible.
+pub fn shard_ranges(
+    num_matches: usize,
+    shard_size: Option<usize>,
+    num_shards: Option<usize>,
+) -> Result<Vec<Range<usize>>, String> {
+    match (shard_size, num_shards) {
+        (Some(_), Some(_)) => Err("--shard-size and --num-shards cannot be combined".to_string()),
+        (Some(0), None) | (None, Some(0)) => Err("Shards must hold at least one match".to_string()),
+        (Some(size), None) => Ok((0..num_matches)
+            .step_by(size)
+            .map(|start| start..(start + size).min(num_matches))
+            .collect()),
+        (None, Some(count)) if count > num_matches => Err(format!(
+            "Cannot split {} matches into {} shards",
+            num_matches, count
+        )),
+        (None, Some(count)) => Ok((0..count)
+            .map(|i| i * num_matches / count..(i + 1) * num_matches / count)
+            .collect()),
+        (None, None) => Ok(std::iter::once(0..num_matches).collect()),
+    }
+}
+
+/// Splits an output path like `out/matches.json.zst` into the directory
+/// and stem (`out/matches`), the extension (`.json`) and the codec.
+fn split_output_path(path: &Path) -> (PathBuf, String, Option<Codec>) {
+    let plain = compress::strip_extension(path);
+    let extension = plain
+        .extension()
+        .map_or(String::new(), |e| format!(".{}", e.to_string_lossy()));
+    (plain.with_extension(""), extension, Codec::of_path(path))
+}
+
+/// The path of shard `index` of `count` for the output `path`.
+pub fn shard_path(path: &Path, index: usize, count: usize) -> PathBuf {
+    let (base, extension, codec) = split_output_path(path);
+    let mut name = base.into_os_string();
+    name.push(format!("-{:05}-of-{:05}{}", index, count, extension));
+    match codec {
+        Some(codec) => compress::with_extension(PathBuf::from(name), codec),
+        None => PathBuf::from(name),
+    }
+}
+
+/// The path of the manifest for the output `path`.
+pub fn manifest_path(path: &Path) -> PathBuf {
+    let mut name = split_output_path(path).0.into_os_string();
+    name.push(MANIFEST_SUFFIX);
+    PathBuf::from(name)
+}
+
+pub fn is_manifest(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(MANIFEST_SUFFIX)
+}
+
+/// Whether `path` is a glob pattern rather than a file.
+pub fn is_glob(path: &Path) -> bool {
+    !path.exists() && path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Check a shard against its checksum and read it.
+fn load_shard(manifest_path: &Path, shard: &ShardInfo) -> Result<Vec<Match>, String> {
+    let path = manifest_path.with_file_name(&shard.path);
+    if sha256_file(&path)? != shard.sha256 {
+        return Err(format!(
+            "Checksum mismatch for shard {}, the file has changed since it was written",
+            path.display()
+        ));
+    }
+    load_matches(&path)
+}
+
+/// Read the shards of a manifest, or the files matching a glob pattern, in
+/// parallel, as one list of matches in shard (or file name) order.
+pub fn load_dataset(path: &Path) -> Result<Vec<Match>, String> {
+    let parts: Vec<Vec<Match>> = if is_manifest(path) {
+        let manifest = Manifest::load(path)?;
+        manifest
+            .shards
+            .par_iter()
+            .map(|shard| load_shard(path, shard))
+            .collect::<Result<_, _>>()?
+    } else {
+        let pattern = path.to_string_lossy();
+        let paths = glob::glob(&pattern)
+            .map_err(|e| format!("Invalid pattern {}: {}", pattern, e))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file() && !is_manifest(path))
+            .collect::<Vec<_>>();
+        if paths.is_empty() {
+            return Err(format!("No files match {}", pattern));
+        }
+        paths
+            .par_iter()
+            .map(|path| load_matches(path))
+            .collect::<Result<_, _>>()?
+    };
+    Ok(parts.into_iter().flatten().collect())
+}
+
+/// Read match `id` from the shard of a manifest whose id range holds it,
+/// or None if no shard does.
+pub fn load_match(path: &Path, id: usize) -> Result<Option<Match>, String> {
+    let manifest = Manifest::load(path)?;
+    let Some(shard) = manifest
+        .shards
+        .iter()
+        .find(|shard| (shard.first_id..=shard.last_id).contains(&id))
+    else {
+        return Ok(None);
+    };
+    Ok(load_shard(path, shard)?.into_iter().find(|m| m.id == id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shard_ranges() {
+        assert_eq!(
+            shard_ranges(10, Some(4), None).unwrap(),
+            vec![0..4, 4..8, 8..10]
+        );
+        assert_eq!(
+            shard_ranges(10, None, Some(3)).unwrap(),
+            vec![0..3, 3..6, 6..10]
+        );
+        assert_eq!(shard_ranges(10, None, None).unwrap(), vec![0..10]);
+        assert!(shard_ranges(10, Some(4), Some(3)).is_err());
+        assert!(shard_ranges(10, None, Some(11)).is_err());
+        assert!(shard_ranges(10, Some(0), None).is_err());
+    }
+
+    #[test]
+    fn test_shard_paths() {
+        let path = Path::new("out/matches.json.zst");
+        assert_eq!(
+            shard_path(path, 3, 16),
+            Path::new("out/matches-00003-of-00016.json.zst")
+        );
+        assert_eq!(manifest_path(path), Path::new("out/matches.manifest.json"));
+        assert_eq!(
+            shard_path(Path::new("m"), 0, 2),
+            Path::new("m-00000-of-00002")
+        );
+        assert!(is_manifest(&manifest_path(path)));
+    }
+
+    #[test]
+    fn test_write_and_load_shards() {
+        let dir = std::env::temp_dir().join(format!("c4-shards-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("matches.json");
+        let mut shards = Vec::new();
+        for (index, range) in shard_ranges(5, Some(2), None)
+            .unwrap()
+            .into_iter()
+            .enumerate()
+        {
+            let shard = shard_path(&path, index, 3);
+            let matches: Vec<Match> = range
+                .clone()
+                .map(|i| Match::new(i + 1, Vec::new()))
+                .collect();
+            std::fs::write(&shard, serde_json::to_string(&matches).unwrap()).unwrap();
+            shards.push(ShardInfo::new(&shard, range.start + 1..=range.end).unwrap());
+        }
+        let manifest_path = manifest_path(&path);
+        Manifest::new("json", shards).save(&manifest_path).unwrap();
+
+        let manifest = Manifest::load(&manifest_path).unwrap();
+        assert_eq!(manifest.num_matches, 5);
+        assert_eq!(manifest.shards[2].path, "matches-00002-of-00003.json");
+        assert_eq!(
+            (manifest.shards[1].first_id, manifest.shards[1].last_id),
+            (3, 4)
+        );
+
+        let ids = |matches: Vec<Match>| matches.iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(load_dataset(&manifest_path).unwrap()),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            ids(load_dataset(&dir.join("matches-*-of-*.json")).unwrap()),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(load_match(&manifest_path, 4).unwrap().unwrap().id, 4);
+        assert!(load_match(&manifest_path, 6).unwrap().is_none());
+
+        // A shard that changed after the manifest was written is rejected
+        std::fs::write(dir.join("matches-00000-of-00003.json"), "[]").unwrap();
+        assert!(load_dataset(&manifest_path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
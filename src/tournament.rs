@@ -0,0 +1,422 @@
+use crate::agent::{parse_agent, Agent};
+use crate::{connect4_match, match_rng, AppConfig, Board, Match, Player};
+use rand::Rng;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Which pairings a tournament plays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// Every agent plays every other agent.
+    RoundRobin,
+    /// The first agent plays each of the others, who don't meet each other.
+    Gauntlet,
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "round-robin" | "roundrobin" => Ok(Schedule::RoundRobin),
+            "gauntlet" => Ok(Schedule::Gauntlet),
+            _ => Err(format!("Unknown tournament schedule: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schedule::RoundRobin => write!(f, "round-robin"),
+            Schedule::Gauntlet => write!(f, "gauntlet"),
+        }
+    }
+}
+
+impl Schedule {
+    /// Index pairs (i, j) with i < j of the agents that meet.
+    fn pairings(&self, agents: usize) -> Vec<(usize, usize)> {
+        match self {
+            Schedule::RoundRobin => (0..agents)
+                .flat_map(|i| (i + 1..agents).map(move |j| (i, j)))
+                .collect(),
+            Schedule::Gauntlet => (1..agents).map(|j| (0, j)).collect(),
+        }
+    }
+}
+
+/// Wins, draws and losses from one side's point of view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Record {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Record {
+    fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    fn points(&self) -> f64 {
+        self.wins as f64 + 0.5 * self.draws as f64
+    }
+
+    fn add(&mut self, other: &Record) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+    }
+}
+
+/// Tournament results: `records[i][j]` is agent i's record against agent j.
+#[derive(Debug, Serialize)]
+pub struct Standings {
+    pub agents: Vec<String>,
+    pub records: Vec<Vec<Record>>,
+    pub elo: Vec<f64>,
+    /// Half-width of the 95% confidence interval of each Elo estimate.
+    pub elo_margin: Vec<f64>,
+}
+
+impl Standings {
+    fn total(&self, i: usize) -> Record {
+        let mut total = Record::default();
+        for record in &self.records[i] {
+            total.add(record);
+        }
+        total
+    }
+}
+
+/// Play `games` games for every pairing of `schedule`, alternating colors
+/// so each agent gets Yellow in half of them, in parallel. Each game's RNG
+/// is seeded from `seed` and the game's number, as in generation, so the
+/// same seed always gives the same standings.
+pub fn run_tournament(
+    agents: &[Box<dyn Agent>],
+    schedule: Schedule,
+    games: usize,
+    seed: u64,
+) -> Standings {
+    let jobs: Vec<(usize, usize, usize)> = schedule
+        .pairings(agents.len())
+        .into_iter()
+        .flat_map(|(i, j)| (0..games).map(move |game| (i, j, game)))
+        .collect();
+
+    let results: Vec<(usize, usize, Option<bool>)> = jobs
+        .into_par_iter()
+        .enumerate()
+        .map(|(index, (i, j, game))| {
+            let mut rng = match_rng(seed, index + 1);
+            let (yellow, red) = if game % 2 == 0 { (i, j) } else { (j, i) };
+            let moves = connect4_match(
+                agents[yellow].as_ref(),
+                agents[red].as_ref(),
+                &Board::new(),
+                &[],
+                &mut rng,
+            );
+            // Some(true) if agent i won, Some(false) if it lost, None for a draw
+            let i_won = Match::new(0, moves).winner().map(|winner| {
+                let i_color = if yellow == i {
+                    Player::Yellow
+                } else {
+                    Player::Red
+                };
+                winner == i_color
+            });
+            (i, j, i_won)
+        })
+        .collect();
+
+    let n = agents.len();
+    let mut records = vec![vec![Record::default(); n]; n];
+    for (i, j, i_won) in results {
+        match i_won {
+            Some(true) => {
+                records[i][j].wins += 1;
+                records[j][i].losses += 1;
+            }
+            Some(false) => {
+                records[i][j].losses += 1;
+                records[j][i].wins += 1;
+            }
+            None => {
+                records[i][j].draws += 1;
+                records[j][i].draws += 1;
+            }
+        }
+    }
+
+    let (elo, elo_margin) = elo_ratings(&records);
+    Standings {
+        agents: agents.iter().map(|agent| agent.name()).collect(),
+        records,
+        elo,
+        elo_margin,
+    }
+}
+
+/// Elo points per unit of the logistic model's natural scale.
+const ELO_SCALE: f64 = 400.0 / std::f64::consts::LN_10;
+
+/// Maximum-likelihood Elo ratings (mean 0) from pairwise records, with the
+/// half-width of their 95% confidence intervals. Each pairing that was played
+/// gets one extra virtual draw so that perfect scores stay finite.
+pub fn elo_ratings(records: &[Vec<Record>]) -> (Vec<f64>, Vec<f64>) {
+    let n = records.len();
+    let games = |i: usize, j: usize| {
+        let played = records[i][j].games();
+        if played == 0 {
+            (0.0, 0.0)
+        } else {
+            (played as f64 + 1.0, records[i][j].points() + 0.5)
+        }
+    };
+    let expected = |theta: &[f64], i: usize, j: usize| 1.0 / (1.0 + (theta[j] - theta[i]).exp());
+
+    // Coordinate-wise Newton steps on the log-likelihood, in natural units.
+    let mut theta = vec![0.0; n];
+    for _ in 0..1000 {
+        let mut largest_step: f64 = 0.0;
+        for i in 0..n {
+            let (mut gradient, mut curvature) = (0.0, 0.0);
+            for j in (0..n).filter(|&j| j != i) {
+                let (played, points) = games(i, j);
+                let p = expected(&theta, i, j);
+                gradient += points - played * p;
+                curvature += played * p * (1.0 - p);
+            }
+            if curvature > 0.0 {
+                let step = gradient / curvature;
+                theta[i] += step;
+                largest_step = largest_step.max(step.abs());
+            }
+        }
+        if largest_step < 1e-9 {
+            break;
+        }
+    }
+    let mean = theta.iter().sum::<f64>() / n.max(1) as f64;
+
+    let margins = (0..n)
+        .map(|i| {
+            let information: f64 = (0..n)
+                .filter(|&j| j != i)
+                .map(|j| {
+                    let p = expected(&theta, i, j);
+                    games(i, j).0 * p * (1.0 - p)
+                })
+                .sum();
+            if information > 0.0 {
+                1.96 * ELO_SCALE / information.sqrt()
+            } else {
+                f64::INFINITY
+            }
+        })
+        .collect();
+    let ratings = theta.iter().map(|t| (t - mean) * ELO_SCALE).collect();
+    (ratings, margins)
+}
+
+/// Plain-text crosstable (points of the row agent against the column agent),
+/// followed by standings sorted by Elo.
+pub fn format_standings(standings: &Standings) -> String {
+    let n = standings.agents.len();
+    let name_width = standings
+        .agents
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    let mut out = String::new();
+
+    out.push_str(&format!("{:<w$}", "", w = name_width + 5));
+    for j in 0..n {
+        out.push_str(&format!(" {:>9}", format!("#{}", j + 1)));
+    }
+    out.push('\n');
+    for i in 0..n {
+        out.push_str(&format!(
+            "#{:<3} {:<w$}",
+            i + 1,
+            standings.agents[i],
+            w = name_width
+        ));
+        for j in 0..n {
+            let record = &standings.records[i][j];
+            let cell = if i == j || record.games() == 0 {
+                "-".to_string()
+            } else {
+                format!("{}/{}", record.points(), record.games())
+            };
+            out.push_str(&format!(" {:>9}", cell));
+        }
+        out.push('\n');
+    }
+
+    out.push('\n');
+    out.push_str(&format!(
+        "{:<4} {:<w$} {:>6} {:>6} {:>6} {:>7} {:>14}\n",
+        "Rank",
+        "Agent",
+        "Wins",
+        "Draws",
+        "Losses",
+        "Score",
+        "Elo (95% CI)",
+        w = name_width
+    ));
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| standings.elo[b].total_cmp(&standings.elo[a]));
+    for (rank, &i) in order.iter().enumerate() {
+        let total = standings.total(i);
+        let score = if total.games() == 0 {
+            0.0
+        } else {
+            100.0 * total.points() / total.games() as f64
+        };
+        out.push_str(&format!(
+            "{:<4} {:<w$} {:>6} {:>6} {:>6} {:>6.1}% {:>6.0} ± {:.0}\n",
+            rank + 1,
+            standings.agents[i],
+            total.wins,
+            total.draws,
+            total.losses,
+            score,
+            standings.elo[i],
+            standings.elo_margin[i],
+            w = name_width
+        ));
+    }
+    out
+}
+
+pub fn do_tournament(config: AppConfig) {
+    let agents: Vec<Box<dyn Agent>> = config
+        .tournament_agents
+        .iter()
+        .map(|spec| {
+            parse_agent(spec).unwrap_or_else(|e| {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            })
+        })
+        .collect();
+
+    let pairings = config.schedule.pairings(agents.len()).len();
+    let seed = config.seed.unwrap_or_else(|| rand::rng().random());
+    println!(
+        "Running {} tournament: {} agents, {} pairings, {} games each, seed {}...",
+        config.schedule,
+        agents.len(),
+        pairings,
+        config.games_per_pairing,
+        seed
+    );
+    let standings = run_tournament(&agents, config.schedule, config.games_per_pairing, seed);
+    print!("{}", format_standings(&standings));
+
+    if let Some(path) = &config.output_file {
+        let written = std::fs::File::create(path)
+            .map_err(|e| format!("Failed to create output file: {}", e))
+            .and_then(|file| {
+                serde_json::to_writer_pretty(file, &standings)
+                    .map_err(|e| format!("Failed to write JSON output: {}", e))
+            });
+        if let Err(e) = written {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        println!("Saved standings to {}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{RandomAgent, TacticalAgent};
+
+    #[test]
+    fn test_pairings() {
+        assert_eq!(
+            Schedule::RoundRobin.pairings(3),
+            vec![(0, 1), (0, 2), (1, 2)]
+        );
+        assert_eq!(Schedule::Gauntlet.pairings(3), vec![(0, 1), (0, 2)]);
+        assert_eq!("gauntlet".parse::<Schedule>(), Ok(Schedule::Gauntlet));
+        assert!("swiss".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn test_elo_ratings() {
+        let even = Record {
+            wins: 10,
+            draws: 0,
+            losses: 10,
+        };
+        let (elo, margin) =
+            elo_ratings(&[vec![Record::default(), even], vec![even, Record::default()]]);
+        assert!(elo[0].abs() < 1e-6 && elo[1].abs() < 1e-6);
+        assert!(margin[0] > 100.0 && margin[0] < 300.0);
+
+        // 75% against an opponent is worth roughly +191 Elo; the virtual
+        // draw pulls it in slightly.
+        let strong = Record {
+            wins: 75,
+            draws: 0,
+            losses: 25,
+        };
+        let weak = Record {
+            wins: 25,
+            draws: 0,
+            losses: 75,
+        };
+        let (elo, _) = elo_ratings(&[
+            vec![Record::default(), strong],
+            vec![weak, Record::default()],
+        ]);
+        let gap = elo[0] - elo[1];
+        assert!(gap > 180.0 && gap < 191.0, "gap {}", gap);
+
+        // A perfect score stays finite.
+        let perfect = Record {
+            wins: 10,
+            draws: 0,
+            losses: 0,
+        };
+        let (elo, _) = elo_ratings(&[
+            vec![Record::default(), perfect],
+            vec![
+                Record {
+                    wins: 0,
+                    draws: 0,
+                    losses: 10,
+                },
+                Record::default(),
+            ],
+        ]);
+        assert!(elo[0].is_finite() && elo[0] > 0.0);
+    }
+
+    #[test]
+    fn test_run_tournament() {
+        let agents: Vec<Box<dyn Agent>> =
+            vec![Box::new(TacticalAgent::default()), Box::new(RandomAgent)];
+        let standings = run_tournament(&agents, Schedule::RoundRobin, 20, 7);
+        assert_eq!(standings.records[0][1].games(), 20);
+        // The seed decides every game
+        let again = run_tournament(&agents, Schedule::RoundRobin, 20, 7);
+        assert_eq!(standings.records, again.records);
+        assert_eq!(standings.records[0][1].wins, standings.records[1][0].losses);
+        assert!(standings.elo[0] > standings.elo[1]);
+        let table = format_standings(&standings);
+        assert!(table.contains("tactical"));
+        assert!(table.contains("Elo (95% CI)"));
+    }
+}
@@ -0,0 +1,249 @@
+use crate::render::{highlights, Renderer};
+use crate::{load_matches, AppConfig, Match, MoveRecord};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor};
+use crossterm::terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use std::io::{self, Write};
+
+/// Navigation state of the replay viewer: which match is shown and how many
+/// of its moves have been applied (0 is the empty board).
+struct Viewer<'a> {
+    matches: &'a [Match],
+    current: usize,
+    ply: usize,
+}
+
+impl<'a> Viewer<'a> {
+    fn new(matches: &'a [Match], current: usize) -> Self {
+        Self {
+            matches,
+            current,
+            ply: 0,
+        }
+    }
+
+    fn moves(&self) -> &'a [MoveRecord] {
+        &self.matches[self.current].moves
+    }
+
+    fn switch_match(&mut self, index: usize) {
+        self.current = index;
+        self.ply = self.ply.min(self.moves().len());
+    }
+
+    /// Apply a key press. Returns false when the viewer should close.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        let len = self.moves().len();
+        match code {
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Char(' ') => {
+                self.ply = (self.ply + 1).min(len)
+            }
+            KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => {
+                self.ply = self.ply.saturating_sub(1)
+            }
+            KeyCode::Home | KeyCode::Char('g') => self.ply = 0,
+            KeyCode::End | KeyCode::Char('G') => self.ply = len,
+            KeyCode::Down | KeyCode::PageDown | KeyCode::Char('n')
+                if self.current + 1 < self.matches.len() =>
+            {
+                self.switch_match(self.current + 1)
+            }
+            KeyCode::Up | KeyCode::PageUp | KeyCode::Char('p') if self.current > 0 => {
+                self.switch_match(self.current - 1)
+            }
+            KeyCode::Esc | KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+}
+
+fn draw<W: Write>(out: &mut W, viewer: &Viewer, renderer: &Renderer) -> io::Result<()> {
+    let moves = viewer.moves();
+    let board = viewer.matches[viewer.current]
+        .start_board()
+        .with_moves(&moves[..viewer.ply]);
+    let marks = highlights(&board, moves, viewer.ply);
+
+    let header = format!(
+        "Match id {} ({}/{})   ply {}/{}",
+        viewer.matches[viewer.current].id,
+        viewer.current + 1,
+        viewer.matches.len(),
+        viewer.ply,
+        moves.len()
+    );
+    queue!(
+        out,
+        terminal::Clear(ClearType::All),
+        cursor::MoveTo(0, 0),
+        Print(header)
+    )?;
+
+    if let Some(header) = renderer.header() {
+        queue!(out, cursor::MoveTo(0, 1), Print(header))?;
+    }
+    for row in 0..6 {
+        queue!(
+            out,
+            cursor::MoveTo(0, row as u16 + 2),
+            Print(renderer.row_label(row)),
+            Print("|")
+        )?;
+        for col in 0..7 {
+            let background = if marks.winning_line.contains(&(row, col)) {
+                Some(Color::DarkGreen)
+            } else if marks.last_drop == Some((row, col)) {
+                Some(Color::DarkBlue)
+            } else if marks.immediate_wins.contains(&(row, col)) {
+                Some(Color::DarkMagenta)
+            } else {
+                None
+            };
+            if let Some(color) = background {
+                queue!(out, SetBackgroundColor(color))?;
+            }
+            let glyph = renderer.cell(board.grid[row][col]);
+            queue!(out, Print(glyph), ResetColor, Print("|"))?;
+        }
+    }
+
+    let mut lines = Vec::new();
+    if let Some(last) = viewer.ply.checked_sub(1).map(|i| &moves[i]) {
+        lines.push(format!(
+            "Move #{} by {:?} in column {}",
+            viewer.ply - 1,
+            last.player,
+            last.usr_move + 1
+        ));
+        if last.has_immediate_win {
+            lines.push(format!(
+                "Immediate wins available before this move: {:?}",
+                last.immediate_win_positions
+            ));
+        }
+        if !marks.winning_line.is_empty() {
+            lines.push(format!("{:?} wins!", last.player));
+        }
+    } else {
+        lines.push("Start position".to_string());
+    }
+    lines.push(String::new());
+    lines.push(
+        "Highlights: blue = last disc, magenta = immediate wins, green = winning line".to_string(),
+    );
+    lines.push("Keys: ←/→ step  Home/End first/last  ↑/↓ previous/next match  q quit".to_string());
+    for (i, line) in lines.iter().enumerate() {
+        queue!(out, cursor::MoveTo(0, i as u16 + 9), Print(line))?;
+    }
+    out.flush()
+}
+
+/// Restores the terminal when the viewer exits, including on panic.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn run_viewer(viewer: &mut Viewer, renderer: &Renderer) -> io::Result<()> {
+    let _guard = TerminalGuard::enter()?;
+    let mut stdout = io::stdout();
+    loop {
+        draw(&mut stdout, viewer, renderer)?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let ctrl_c =
+                key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+            if ctrl_c || !viewer.handle_key(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+pub fn do_view(config: AppConfig) {
+    let input_file = config.input_file.as_ref().unwrap();
+    let all_matches = load_matches(input_file).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    if all_matches.is_empty() {
+        eprintln!("Error: No matches in {}", input_file.display());
+        std::process::exit(1);
+    }
+
+    let start = match config.id {
+        Some(id) => all_matches
+            .iter()
+            .position(|m| m.id == id)
+            .unwrap_or_else(|| {
+                eprintln!("Error: Match ID {} not found", id);
+                std::process::exit(1);
+            }),
+        None => 0,
+    };
+
+    let mut viewer = Viewer::new(&all_matches, start);
+    let renderer = Renderer::new(config.render, config.coordinates);
+    if let Err(e) = run_viewer(&mut viewer, &renderer) {
+        eprintln!("Error: Terminal viewer failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Player;
+
+    #[test]
+    fn test_viewer_navigation() {
+        let matches = vec![
+            Match::new(
+                1,
+                vec![
+                    MoveRecord::new(3, Player::Yellow),
+                    MoveRecord::new(2, Player::Red),
+                ],
+            ),
+            Match::new(2, vec![MoveRecord::new(0, Player::Yellow)]),
+        ];
+        let mut viewer = Viewer::new(&matches, 0);
+
+        viewer.handle_key(KeyCode::Left);
+        assert_eq!(viewer.ply, 0);
+        viewer.handle_key(KeyCode::Right);
+        assert_eq!(viewer.ply, 1);
+        viewer.handle_key(KeyCode::End);
+        assert_eq!(viewer.ply, 2);
+        viewer.handle_key(KeyCode::Right);
+        assert_eq!(viewer.ply, 2);
+
+        // Switching to a shorter match clamps the ply.
+        viewer.handle_key(KeyCode::Down);
+        assert_eq!((viewer.current, viewer.ply), (1, 1));
+        viewer.handle_key(KeyCode::Down);
+        assert_eq!(viewer.current, 1);
+        viewer.handle_key(KeyCode::Home);
+        assert_eq!(viewer.ply, 0);
+        viewer.handle_key(KeyCode::Up);
+        assert_eq!(viewer.current, 0);
+
+        assert!(!viewer.handle_key(KeyCode::Char('q')));
+    }
+}